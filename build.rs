@@ -0,0 +1,78 @@
+use std::env;
+use std::path::PathBuf;
+
+/// The vendored query protos the reflection service describes, by the name
+/// of the descriptor set each is compiled into. A service registers its set
+/// only when it is mounted.
+const PROTOS: &[(&str, &str)] = &[
+    ("client", "proto/ibc/core/client/v1/query.proto"),
+    ("connection", "proto/ibc/core/connection/v1/query.proto"),
+    ("channel", "proto/ibc/core/channel/v1/query.proto"),
+    ("transfer", "proto/ibc/applications/transfer/v1/query.proto"),
+    (
+        "ica_controller",
+        "proto/ibc/applications/interchain_accounts/controller/v1/query.proto",
+    ),
+    (
+        "ica_host",
+        "proto/ibc/applications/interchain_accounts/host/v1/query.proto",
+    ),
+    ("fee", "proto/ibc/applications/fee/v1/query.proto"),
+    (
+        "tendermint",
+        "proto/cosmos/base/tendermint/v1beta1/query.proto",
+    ),
+    ("staking", "proto/cosmos/staking/v1beta1/query.proto"),
+    ("auth", "proto/cosmos/auth/v1beta1/query.proto"),
+];
+
+/// The query protos whose responses the REST gateway renders as JSON.
+const GATEWAY_PROTOS: &[&str] = &[
+    "proto/ibc/core/client/v1/query.proto",
+    "proto/ibc/core/connection/v1/query.proto",
+    "proto/ibc/core/channel/v1/query.proto",
+];
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+
+    // Compile with the vendored protoc and well-known types, so building
+    // does not depend on a system protoc.
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    let well_known_types = protoc_bin_vendored::include_path()?;
+
+    // Only the descriptor set is used: the messages and services come from
+    // ibc-proto.
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let includes = [PathBuf::from("proto"), well_known_types];
+    for (name, proto) in PROTOS {
+        tonic_build::configure()
+            .build_client(false)
+            .build_server(false)
+            .out_dir(&out_dir)
+            .file_descriptor_set_path(out_dir.join(format!("{}_descriptor.bin", name)))
+            .compile(&[proto], &includes)?;
+    }
+
+    // The gateway renders responses through messages of its own, generated
+    // with the proto3 JSON mapping the Cosmos SDK gateway uses: proto field
+    // names, defaults included and enums by name.
+    let json_dir = out_dir.join("json");
+    std::fs::create_dir_all(&json_dir)?;
+    let json_descriptor = json_dir.join("descriptor.bin");
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .out_dir(&json_dir)
+        .extern_path(".google.protobuf.Any", "crate::json::Any")
+        .file_descriptor_set_path(&json_descriptor)
+        .compile(GATEWAY_PROTOS, &includes)?;
+    pbjson_build::Builder::new()
+        .out_dir(&json_dir)
+        .register_descriptors(&std::fs::read(&json_descriptor)?)?
+        .extern_path(".google.protobuf.Any", "crate::json::Any")
+        .preserve_proto_field_names()
+        .emit_fields()
+        .build(&[".ibc.core", ".cosmos.base.query"])?;
+    Ok(())
+}
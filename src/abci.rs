@@ -0,0 +1,324 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::{Method, Request, Response, StatusCode};
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Body;
+use ibc_proto::cosmos::base::tendermint::v1beta1::AbciQueryResponse;
+use serde_json::{json, Value};
+
+use crate::abci_query::AbciQuery;
+use crate::catch_panic::{catch_unwind, install_location_hook, panic_status};
+use crate::error::ServerError;
+use crate::instrument::InstrumentedStore;
+use crate::AsyncIbcStore;
+
+/// Requests are a few hundred bytes; anything much larger is not ours.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Serves Tendermint's `abci_query` JSON-RPC method on `POST /` for the
+/// `store/ibc/key` path, which relayers fetch proofs with, until the returned
+/// task is aborted. It has no authentication and is meant to be bound to a
+/// local address.
+pub(crate) fn spawn_abci_server<Store>(
+    addr: SocketAddr,
+    store: Arc<Store>,
+) -> crate::Result<tokio::task::JoinHandle<()>>
+where
+    Store: AsyncIbcStore + 'static,
+{
+    let builder = hyper::Server::try_bind(&addr).map_err(|e| {
+        ServerError::Config(format!(
+            "failed to bind ABCI query endpoint {}: {}",
+            addr, e
+        ))
+    })?;
+
+    install_location_hook();
+    let store = Arc::new(InstrumentedStore::new(store));
+    let make_service = make_service_fn(move |_conn| {
+        let store = Arc::clone(&store);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let store = Arc::clone(&store);
+                async move { Ok::<_, Infallible>(handle(store.as_ref(), req).await) }
+            }))
+        }
+    });
+
+    log::info!("ABCI query endpoint listening on {}", addr);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = builder.serve(make_service).await {
+            log::error!("ABCI query endpoint failed: {}", e);
+        }
+    }))
+}
+
+async fn handle<Store: AsyncIbcStore>(store: &Store, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::POST {
+        return Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .body(Body::empty())
+            .expect("valid response");
+    }
+
+    let body = match read_body(req.into_body()).await {
+        Some(body) => body,
+        None => {
+            return Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(Body::empty())
+                .expect("valid response")
+        }
+    };
+    let reply = match serde_json::from_slice::<Value>(&body) {
+        // A panicking store fails the call, not the connection.
+        Ok(request) => match catch_unwind(call(store, &request)).await {
+            Ok(reply) => reply,
+            Err(panic) => {
+                let id = request.get("id").cloned().unwrap_or(Value::Null);
+                let status = panic_status("abci_query", panic);
+                error_reply(id, INTERNAL_ERROR, status.message())
+            }
+        },
+        Err(e) => error_reply(Value::Null, PARSE_ERROR, &e.to_string()),
+    };
+
+    Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(Body::from(reply.to_string()))
+        .expect("valid response")
+}
+
+/// Reads the whole body, or `None` if it is larger than `MAX_BODY_SIZE`.
+async fn read_body(mut body: Body) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.ok()?;
+        if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+            return None;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Some(bytes)
+}
+
+async fn call<Store: AsyncIbcStore>(store: &Store, request: &Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(method) => method,
+        None => return error_reply(id, INVALID_REQUEST, "missing method"),
+    };
+    if method != "abci_query" {
+        return error_reply(
+            id,
+            METHOD_NOT_FOUND,
+            &format!("method {} not found", method),
+        );
+    }
+
+    let query = match parse_query(request.get("params").unwrap_or(&Value::Null)) {
+        Ok(query) => query,
+        Err(e) => return error_reply(id, INVALID_PARAMS, &e),
+    };
+    let response = query.run(store).await;
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "result": { "response": response_json(&response) },
+    })
+}
+
+fn error_reply(id: Value, code: i64, data: &str) -> Value {
+    let message = match code {
+        PARSE_ERROR => "Parse error",
+        INVALID_REQUEST => "Invalid Request",
+        METHOD_NOT_FOUND => "Method not found",
+        INVALID_PARAMS => "Invalid params",
+        _ => "Internal error",
+    };
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message, "data": data },
+    })
+}
+
+/// Parses the params of an `abci_query` call, as sent by Tendermint RPC
+/// clients: `{"path": "store/ibc/key", "data": "<hex key>", "height": "0", "prove": true}`.
+fn parse_query(params: &Value) -> Result<AbciQuery, String> {
+    let path = params
+        .get("path")
+        .and_then(Value::as_str)
+        .ok_or("missing path")?;
+
+    let data = params.get("data").and_then(Value::as_str).unwrap_or("");
+    let key = hex::decode(data.trim_start_matches("0x"))
+        .map_err(|e| format!("invalid data {:?}: {}", data, e))?;
+
+    // Heights are strings in Tendermint's JSON encoding, but accept
+    // numbers too.
+    let height = match params.get("height") {
+        None | Some(Value::Null) => 0,
+        Some(Value::String(height)) => height
+            .parse()
+            .map_err(|e| format!("invalid height {:?}: {}", height, e))?,
+        Some(height) => height
+            .as_u64()
+            .ok_or_else(|| format!("invalid height {}", height))?,
+    };
+
+    let prove = params
+        .get("prove")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    Ok(AbciQuery::new(path, key, height, prove))
+}
+
+/// Renders `response` in Tendermint's JSON encoding, with 64-bit integers as
+/// strings and bytes in base64.
+fn response_json(response: &AbciQueryResponse) -> Value {
+    let proof_ops = response.proof_ops.as_ref().map(|proof_ops| {
+        let ops: Vec<Value> = proof_ops
+            .ops
+            .iter()
+            .map(|op| {
+                json!({
+                    "type": op.r#type,
+                    "key": base64::encode(&op.key),
+                    "data": base64::encode(&op.data),
+                })
+            })
+            .collect();
+        json!({ "ops": ops })
+    });
+
+    json!({
+        "code": response.code,
+        "log": response.log,
+        "info": response.info,
+        "index": response.index.to_string(),
+        "key": base64::encode(&response.key),
+        "value": base64::encode(&response.value),
+        "proofOps": proof_ops,
+        "height": response.height.to_string(),
+        "codespace": response.codespace,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc::core::ics02_client::{client_state::ClientState, consensus_state::ConsensusState};
+    use ibc::core::ics24_host::path::{ClientConsensusStatePath, ClientStatePath};
+    use ibc_proto::ibc::core::commitment::v1::MerkleProof;
+    use ibc_proto::ics23::CommitmentProof;
+
+    use super::*;
+    use crate::abci_query::IBC_KEY_PATH;
+    use crate::types::{Path, RawQuery, StoreHeight};
+    use crate::{BlockingIbcStore, IbcStore};
+
+    /// A store panicking on every raw read.
+    struct PanickingStore;
+
+    impl IbcStore for PanickingStore {
+        fn get_client_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientStatePath,
+        ) -> crate::Result<Option<Box<dyn ClientState>>> {
+            Ok(None)
+        }
+
+        fn get_consensus_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientConsensusStatePath,
+        ) -> crate::Result<Option<Box<dyn ConsensusState>>> {
+            Ok(None)
+        }
+
+        fn get_paths_by_prefix(&self, _key_prefix: &Path) -> crate::Result<Vec<Path>> {
+            Ok(vec![])
+        }
+
+        fn current_height(&self) -> u64 {
+            1
+        }
+
+        fn get_raw(&self, _height: StoreHeight, _path: &Path) -> crate::Result<Option<Vec<u8>>> {
+            panic!("corrupt store")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_panic_fails_the_call() {
+        let store = BlockingIbcStore::new(PanickingStore);
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "abci_query",
+            "params": {
+                "path": IBC_KEY_PATH,
+                "data": hex::encode("clients/07-tendermint-0/clientState"),
+            },
+        });
+        let request = Request::post("/")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = handle(&store, request).await;
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let reply: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reply["id"], 1);
+        assert_eq!(reply["error"]["code"], INTERNAL_ERROR);
+    }
+
+    #[test]
+    fn test_parse_abci_query() {
+        let key = b"clients/07-tendermint-0/clientState";
+        let query = parse_query(&json!({
+            "path": "/store/ibc/key",
+            "data": hex::encode_upper(key),
+            "height": "42",
+            "prove": true,
+        }))
+        .unwrap();
+        assert_eq!(query, AbciQuery::new(IBC_KEY_PATH, key.to_vec(), 42, true));
+
+        let query = parse_query(&json!({ "path": "store/bank/key", "height": 0 })).unwrap();
+        assert_eq!(query.height, StoreHeight::Latest);
+
+        assert!(parse_query(&json!({ "path": IBC_KEY_PATH, "data": "zz" })).is_err());
+    }
+
+    #[test]
+    fn test_abci_query_response_json() {
+        let query = AbciQuery::new(IBC_KEY_PATH, b"ab".to_vec(), 0, true);
+        let response = response_json(&query.response(RawQuery {
+            value: Some(vec![1, 2, 3]),
+            height: 7,
+            proof: Some(MerkleProof {
+                proofs: vec![CommitmentProof::default(), CommitmentProof::default()],
+            }),
+        }));
+        assert_eq!(response["code"], 0);
+        assert_eq!(response["index"], "0");
+        assert_eq!(response["value"], "AQID");
+        assert_eq!(response["height"], "7");
+        let ops = response["proofOps"]["ops"].as_array().unwrap();
+        assert_eq!(ops[0]["type"], "ics23:iavl");
+        assert_eq!(ops[0]["key"], base64::encode("ab"));
+        assert_eq!(ops[1]["type"], "ics23:simple");
+        assert_eq!(ops[1]["key"], base64::encode("ibc"));
+    }
+}
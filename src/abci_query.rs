@@ -0,0 +1,156 @@
+use ibc_proto::cosmos::base::tendermint::v1beta1::{AbciQueryResponse, ProofOp, ProofOps};
+use prost::Message;
+
+use crate::error::{ServerError, ERROR_DOMAIN};
+use crate::types::{Path, RawQuery, StoreHeight};
+use crate::AsyncIbcStore;
+
+/// The ABCI query path Cosmos SDK chains serve the IBC store keys on.
+pub(crate) const IBC_KEY_PATH: &str = "store/ibc/key";
+
+/// The name of the IBC store in the multistore of a Cosmos SDK chain, which
+/// keys the proof of the IBC store root.
+const IBC_STORE_NAME: &[u8] = b"ibc";
+
+/// An ABCI query for a key of the IBC store, as relayers fetch proofs with,
+/// over Tendermint RPC or the `ABCIQuery` method of the tendermint service.
+#[derive(Debug, PartialEq)]
+pub(crate) struct AbciQuery {
+    pub(crate) path: String,
+    pub(crate) key: Vec<u8>,
+    pub(crate) height: StoreHeight,
+    pub(crate) prove: bool,
+}
+
+impl AbciQuery {
+    /// A query for `key` on `path`, with or without its leading `/`, at
+    /// `height`, where 0 stands for the latest height.
+    pub(crate) fn new(path: &str, key: Vec<u8>, height: u64, prove: bool) -> Self {
+        Self {
+            path: path.trim_start_matches('/').to_owned(),
+            key,
+            height: match height {
+                0 => StoreHeight::Latest,
+                height => StoreHeight::Stable(height),
+            },
+            prove,
+        }
+    }
+
+    /// The ICS-24 path of the queried key.
+    pub(crate) fn ibc_path(&self) -> crate::Result<Path> {
+        if self.path != IBC_KEY_PATH {
+            return Err(ServerError::InvalidArgument(format!(
+                "unsupported query path {:?}",
+                self.path
+            )));
+        }
+        Path::try_from(self.key.as_slice())
+    }
+
+    /// Answers the query from `store`. Failures are reported in the
+    /// response, as applications report them to ABCI queries.
+    pub(crate) async fn run<Store: AsyncIbcStore>(&self, store: &Store) -> AbciQueryResponse {
+        let path = match self.ibc_path() {
+            Ok(path) => path,
+            Err(e) => return self.error_response(&e),
+        };
+        match store.query_raw(self.height, &path, self.prove).await {
+            Ok(raw) => self.response(raw),
+            Err(e) => self.error_response(&e),
+        }
+    }
+
+    /// Renders `raw` as a Tendermint `ResponseQuery`. The proofs become the
+    /// proof ops the Cosmos SDK returns: an `ics23:iavl` op for the key in
+    /// the IBC store, then `ics23:simple` ops for the store in the app hash.
+    pub(crate) fn response(&self, raw: RawQuery) -> AbciQueryResponse {
+        let proof_ops = raw.proof.map(|proof| ProofOps {
+            ops: proof
+                .proofs
+                .iter()
+                .enumerate()
+                .map(|(i, proof)| {
+                    let (op_type, key) = if i == 0 {
+                        ("ics23:iavl", self.key.as_slice())
+                    } else {
+                        ("ics23:simple", IBC_STORE_NAME)
+                    };
+                    ProofOp {
+                        r#type: op_type.to_owned(),
+                        key: key.to_vec(),
+                        data: proof.encode_to_vec(),
+                    }
+                })
+                .collect(),
+        });
+
+        AbciQueryResponse {
+            key: self.key.clone(),
+            value: raw.value.unwrap_or_default(),
+            proof_ops,
+            height: i64::try_from(raw.height).unwrap_or(i64::MAX),
+            ..Default::default()
+        }
+    }
+
+    /// Renders a failed query the way applications report errors to ABCI
+    /// queries, with a non-zero code and the error in the log.
+    fn error_response(&self, error: &ServerError) -> AbciQueryResponse {
+        let height = match self.height {
+            StoreHeight::Latest => 0,
+            StoreHeight::Stable(height) => i64::try_from(height).unwrap_or(i64::MAX),
+        };
+        AbciQueryResponse {
+            code: 1,
+            log: error.to_string(),
+            info: error.reason().to_owned(),
+            key: self.key.clone(),
+            height,
+            codespace: ERROR_DOMAIN.to_owned(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_proto::ibc::core::commitment::v1::MerkleProof;
+    use ibc_proto::ics23::CommitmentProof;
+
+    use super::*;
+
+    #[test]
+    fn test_ibc_path() {
+        let key = b"clients/07-tendermint-0/clientState".to_vec();
+        let query = AbciQuery::new("/store/ibc/key", key, 42, true);
+        assert_eq!(query.height, StoreHeight::Stable(42));
+        assert_eq!(
+            query.ibc_path().unwrap().to_string(),
+            "clients/07-tendermint-0/clientState"
+        );
+
+        let query = AbciQuery::new("store/bank/key", vec![], 0, false);
+        assert_eq!(query.height, StoreHeight::Latest);
+        assert!(query.ibc_path().is_err());
+    }
+
+    #[test]
+    fn test_abci_query_response() {
+        let query = AbciQuery::new(IBC_KEY_PATH, b"ab".to_vec(), 0, true);
+        let response = query.response(RawQuery {
+            value: Some(vec![1, 2, 3]),
+            height: 7,
+            proof: Some(MerkleProof {
+                proofs: vec![CommitmentProof::default(), CommitmentProof::default()],
+            }),
+        });
+        assert_eq!(response.value, vec![1, 2, 3]);
+        assert_eq!(response.height, 7);
+        let ops = response.proof_ops.unwrap().ops;
+        assert_eq!(ops[0].r#type, "ics23:iavl");
+        assert_eq!(ops[0].key, b"ab");
+        assert_eq!(ops[1].r#type, "ics23:simple");
+        assert_eq!(ops[1].key, b"ibc");
+    }
+}
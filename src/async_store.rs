@@ -0,0 +1,473 @@
+use std::sync::Arc;
+
+use ibc::core::ics02_client::{client_state::ClientState, consensus_state::ConsensusState};
+use ibc::core::ics03_connection::connection::ConnectionEnd;
+use ibc::core::ics04_channel::channel::ChannelEnd;
+use ibc::core::ics04_channel::commitment::{AcknowledgementCommitment, PacketCommitment};
+use ibc::core::ics24_host::identifier::ConnectionId;
+use ibc::core::ics24_host::path::{
+    AcksPath, ChannelEndsPath, ClientConnectionsPath, ClientConsensusStatePath, ClientStatePath,
+    CommitmentsPath, ConnectionsPath, ReceiptsPath,
+};
+
+use ibc_proto::ibc::core::channel::v1::Channel as RawChannel;
+use ibc_proto::ibc::core::connection::v1::ConnectionEnd as RawConnectionEnd;
+
+use crate::catch_panic;
+use crate::types::{Path, RawQuery, StoreHeight};
+use crate::{decode_connection_ids, decode_raw, IbcStore, Result, ServerError};
+
+/// The non-blocking counterpart of [`IbcStore`], used by the gRPC handlers.
+/// Any `IbcStore` can be turned into one with [`BlockingIbcStore`].
+#[tonic::async_trait]
+pub trait AsyncIbcStore: Sync + Send {
+    async fn get_client_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientStatePath,
+    ) -> Result<Option<Box<dyn ClientState>>>;
+
+    async fn get_consensus_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientConsensusStatePath,
+    ) -> Result<Option<Box<dyn ConsensusState>>>;
+
+    async fn get_connection_end(
+        &self,
+        height: StoreHeight,
+        path: &ConnectionsPath,
+    ) -> Result<Option<ConnectionEnd>> {
+        let path = Path::from(path.clone());
+        decode_raw::<_, RawConnectionEnd>(&path, self.get_raw(height, &path).await?)
+    }
+
+    /// The default decodes the `ClientPaths` stored by ibc-go.
+    async fn get_connection_ids(
+        &self,
+        height: StoreHeight,
+        path: &ClientConnectionsPath,
+    ) -> Result<Vec<ConnectionId>> {
+        let path = Path::from(path.clone());
+        decode_connection_ids(&path, self.get_raw(height, &path).await?)
+    }
+
+    async fn get_acknowledgement_commitment(
+        &self,
+        height: StoreHeight,
+        path: &AcksPath,
+    ) -> Result<Option<AcknowledgementCommitment>> {
+        Ok(self
+            .get_raw(height, &path.clone().into())
+            .await?
+            .map(AcknowledgementCommitment::from))
+    }
+
+    async fn get_acknowledgement_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[AcksPath],
+    ) -> Result<Vec<Option<AcknowledgementCommitment>>> {
+        let mut commitments = Vec::with_capacity(paths.len());
+        for path in paths {
+            commitments.push(self.get_acknowledgement_commitment(height, path).await?);
+        }
+        Ok(commitments)
+    }
+
+    async fn get_channel_end(
+        &self,
+        height: StoreHeight,
+        path: &ChannelEndsPath,
+    ) -> Result<Option<ChannelEnd>> {
+        let path = Path::from(path.clone());
+        decode_raw::<_, RawChannel>(&path, self.get_raw(height, &path).await?)
+    }
+
+    async fn get_channel_paths_by_connection(
+        &self,
+        _height: StoreHeight,
+        _conn_id: &ConnectionId,
+    ) -> Result<Option<Vec<ChannelEndsPath>>> {
+        Ok(None)
+    }
+
+    /// The default treats any value stored under the receipt path as a
+    /// receipt.
+    async fn get_opt(&self, height: StoreHeight, path: &ReceiptsPath) -> Result<Option<()>> {
+        Ok(self
+            .get_raw(height, &path.clone().into())
+            .await?
+            .map(|_| ()))
+    }
+
+    async fn get_opts(
+        &self,
+        height: StoreHeight,
+        paths: &[ReceiptsPath],
+    ) -> Result<Vec<Option<()>>> {
+        let mut receipts = Vec::with_capacity(paths.len());
+        for path in paths {
+            receipts.push(self.get_opt(height, path).await?);
+        }
+        Ok(receipts)
+    }
+
+    async fn get_packet_commitment(
+        &self,
+        height: StoreHeight,
+        path: &CommitmentsPath,
+    ) -> Result<Option<PacketCommitment>> {
+        Ok(self
+            .get_raw(height, &path.clone().into())
+            .await?
+            .map(PacketCommitment::from))
+    }
+
+    async fn get_packet_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[CommitmentsPath],
+    ) -> Result<Vec<Option<PacketCommitment>>> {
+        let mut commitments = Vec::with_capacity(paths.len());
+        for path in paths {
+            commitments.push(self.get_packet_commitment(height, path).await?);
+        }
+        Ok(commitments)
+    }
+
+    async fn get_paths_by_prefix(&self, key_prefix: &Path) -> Result<Vec<Path>>;
+
+    async fn current_height(&self) -> u64;
+
+    /// Returns the raw bytes committed under `path`, or `None` if nothing is
+    /// stored there. The connection, channel and packet getters default to
+    /// decoding its values as [`IbcStore::get_raw`] describes. The default
+    /// fails with `Unimplemented`.
+    async fn get_raw(&self, _height: StoreHeight, _path: &Path) -> Result<Option<Vec<u8>>> {
+        Err(ServerError::Unimplemented("raw values".to_owned()))
+    }
+
+    /// The default answers from [`get_raw`](Self::get_raw), without proofs.
+    async fn query_raw(&self, height: StoreHeight, path: &Path, _prove: bool) -> Result<RawQuery> {
+        let height = match height {
+            StoreHeight::Latest => self.current_height().await,
+            StoreHeight::Stable(height) => height,
+        };
+        Ok(RawQuery {
+            value: self.get_raw(StoreHeight::Stable(height), path).await?,
+            height,
+            proof: None,
+        })
+    }
+}
+
+/// Adapts a synchronous [`IbcStore`] to [`AsyncIbcStore`] by running every
+/// call on tokio's blocking thread pool, so a slow backend never stalls the
+/// executor serving other requests.
+pub struct BlockingIbcStore<Store> {
+    inner: Arc<Store>,
+}
+
+impl<Store> BlockingIbcStore<Store>
+where
+    Store: IbcStore + 'static,
+{
+    pub fn new(store: Store) -> Self {
+        Self {
+            inner: Arc::new(store),
+        }
+    }
+
+    async fn spawn_blocking<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&Store) -> T + Send + 'static,
+    {
+        let store = Arc::clone(&self.inner);
+        catch_panic::spawn_blocking(move || f(&store)).await
+    }
+}
+
+#[tonic::async_trait]
+impl<Store> AsyncIbcStore for BlockingIbcStore<Store>
+where
+    Store: IbcStore + 'static,
+{
+    async fn get_client_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientStatePath,
+    ) -> Result<Option<Box<dyn ClientState>>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_client_state(height, &path))
+            .await
+    }
+
+    async fn get_consensus_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientConsensusStatePath,
+    ) -> Result<Option<Box<dyn ConsensusState>>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_consensus_state(height, &path))
+            .await
+    }
+
+    async fn get_connection_end(
+        &self,
+        height: StoreHeight,
+        path: &ConnectionsPath,
+    ) -> Result<Option<ConnectionEnd>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_connection_end(height, &path))
+            .await
+    }
+
+    async fn get_connection_ids(
+        &self,
+        height: StoreHeight,
+        path: &ClientConnectionsPath,
+    ) -> Result<Vec<ConnectionId>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_connection_ids(height, &path))
+            .await
+    }
+
+    async fn get_acknowledgement_commitment(
+        &self,
+        height: StoreHeight,
+        path: &AcksPath,
+    ) -> Result<Option<AcknowledgementCommitment>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_acknowledgement_commitment(height, &path))
+            .await
+    }
+
+    async fn get_acknowledgement_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[AcksPath],
+    ) -> Result<Vec<Option<AcknowledgementCommitment>>> {
+        let paths = paths.to_vec();
+        self.spawn_blocking(move |store| store.get_acknowledgement_commitments(height, &paths))
+            .await
+    }
+
+    async fn get_channel_end(
+        &self,
+        height: StoreHeight,
+        path: &ChannelEndsPath,
+    ) -> Result<Option<ChannelEnd>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_channel_end(height, &path))
+            .await
+    }
+
+    async fn get_channel_paths_by_connection(
+        &self,
+        height: StoreHeight,
+        conn_id: &ConnectionId,
+    ) -> Result<Option<Vec<ChannelEndsPath>>> {
+        let conn_id = conn_id.clone();
+        self.spawn_blocking(move |store| store.get_channel_paths_by_connection(height, &conn_id))
+            .await
+    }
+
+    async fn get_opt(&self, height: StoreHeight, path: &ReceiptsPath) -> Result<Option<()>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_opt(height, &path))
+            .await
+    }
+
+    async fn get_opts(
+        &self,
+        height: StoreHeight,
+        paths: &[ReceiptsPath],
+    ) -> Result<Vec<Option<()>>> {
+        let paths = paths.to_vec();
+        self.spawn_blocking(move |store| store.get_opts(height, &paths))
+            .await
+    }
+
+    async fn get_packet_commitment(
+        &self,
+        height: StoreHeight,
+        path: &CommitmentsPath,
+    ) -> Result<Option<PacketCommitment>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_packet_commitment(height, &path))
+            .await
+    }
+
+    async fn get_packet_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[CommitmentsPath],
+    ) -> Result<Vec<Option<PacketCommitment>>> {
+        let paths = paths.to_vec();
+        self.spawn_blocking(move |store| store.get_packet_commitments(height, &paths))
+            .await
+    }
+
+    async fn get_paths_by_prefix(&self, key_prefix: &Path) -> Result<Vec<Path>> {
+        let key_prefix = key_prefix.clone();
+        self.spawn_blocking(move |store| store.get_paths_by_prefix(&key_prefix))
+            .await
+    }
+
+    async fn current_height(&self) -> u64 {
+        self.spawn_blocking(|store| store.current_height()).await
+    }
+
+    async fn get_raw(&self, height: StoreHeight, path: &Path) -> Result<Option<Vec<u8>>> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.get_raw(height, &path))
+            .await
+    }
+
+    async fn query_raw(&self, height: StoreHeight, path: &Path, prove: bool) -> Result<RawQuery> {
+        let path = path.clone();
+        self.spawn_blocking(move |store| store.query_raw(height, &path, prove))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ibc::core::ics04_channel::packet::Sequence;
+    use ibc::core::ics24_host::identifier::{ChannelId, PortId};
+    use ibc_proto::ibc::core::channel::v1::Counterparty as RawCounterparty;
+    use ibc_proto::ibc::core::connection::v1::ClientPaths as RawClientPaths;
+    use prost::Message;
+
+    use super::*;
+
+    /// An async store implementing `get_raw` alone for the getters with
+    /// defaults.
+    struct RawAsyncStore(BTreeMap<String, Vec<u8>>);
+
+    #[tonic::async_trait]
+    impl AsyncIbcStore for RawAsyncStore {
+        async fn get_client_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientStatePath,
+        ) -> Result<Option<Box<dyn ClientState>>> {
+            Ok(None)
+        }
+
+        async fn get_consensus_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientConsensusStatePath,
+        ) -> Result<Option<Box<dyn ConsensusState>>> {
+            Ok(None)
+        }
+
+        async fn get_paths_by_prefix(&self, _key_prefix: &Path) -> Result<Vec<Path>> {
+            Ok(vec![])
+        }
+
+        async fn current_height(&self) -> u64 {
+            9
+        }
+
+        async fn get_raw(&self, _height: StoreHeight, path: &Path) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.get(&path.to_string()).cloned())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_getters_from_raw_values() {
+        let port_id = PortId::transfer();
+        let channel_id = ChannelId::new(0);
+        let channel = RawChannel {
+            state: 3,
+            ordering: 1,
+            counterparty: Some(RawCounterparty {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-1".to_owned(),
+            }),
+            connection_hops: vec!["connection-0".to_owned()],
+            version: "ics20-1".to_owned(),
+        };
+        let channel_path = ChannelEndsPath(port_id.clone(), channel_id.clone());
+        let commitment_path = CommitmentsPath {
+            port_id: port_id.clone(),
+            channel_id: channel_id.clone(),
+            sequence: Sequence::from(1),
+        };
+        let ack_path = AcksPath {
+            port_id: port_id.clone(),
+            channel_id: channel_id.clone(),
+            sequence: Sequence::from(1),
+        };
+        let receipt_path = ReceiptsPath {
+            port_id,
+            channel_id,
+            sequence: Sequence::from(1),
+        };
+        let store = RawAsyncStore(BTreeMap::from([
+            (channel_path.to_string(), channel.encode_to_vec()),
+            (commitment_path.to_string(), vec![0xab; 32]),
+            (ack_path.to_string(), vec![0xcd; 32]),
+            (receipt_path.to_string(), vec![1]),
+            (
+                "clients/07-tendermint-0/connections".to_owned(),
+                RawClientPaths {
+                    paths: vec!["connection-0".to_owned()],
+                }
+                .encode_to_vec(),
+            ),
+        ]));
+
+        let channel_end = store
+            .get_channel_end(StoreHeight::Latest, &channel_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(channel_end.connection_hops().len(), 1);
+        let commitment = store
+            .get_packet_commitment(StoreHeight::Latest, &commitment_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(commitment.into_vec(), vec![0xab; 32]);
+        let ack = store
+            .get_acknowledgement_commitment(StoreHeight::Latest, &ack_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ack.into_vec(), vec![0xcd; 32]);
+        assert_eq!(
+            store
+                .get_opt(StoreHeight::Latest, &receipt_path)
+                .await
+                .unwrap(),
+            Some(())
+        );
+        let connection_ids = store
+            .get_connection_ids(
+                StoreHeight::Latest,
+                &ClientConnectionsPath("07-tendermint-0".parse().unwrap()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(connection_ids, vec![ConnectionId::new(0)]);
+        assert!(store
+            .get_connection_end(StoreHeight::Latest, &ConnectionsPath(ConnectionId::new(0)))
+            .await
+            .unwrap()
+            .is_none());
+
+        let raw = store
+            .query_raw(StoreHeight::Latest, &Path::from(channel_path), false)
+            .await
+            .unwrap();
+        assert_eq!(raw.height, 9);
+        assert_eq!(raw.value, Some(channel.encode_to_vec()));
+    }
+}
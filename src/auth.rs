@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use futures::future::{self, Either, Ready};
+use sha2::{Digest, Sha256};
+use tonic::body::BoxBody;
+use tonic::{Request, Status};
+use tower::{Layer, Service};
+
+use crate::health::is_health_check;
+use crate::metrics::METRICS;
+
+/// The lowercase hex SHA-256 of `bytes`.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The label of the API key a request was authenticated with. It is put into
+/// the request extensions, where handlers can read it with [`api_key_label`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKeyLabel(pub String);
+
+impl std::fmt::Display for ApiKeyLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Returns the label of the API key a request was authenticated with.
+pub fn api_key_label<T>(request: &Request<T>) -> Option<&ApiKeyLabel> {
+    request.extensions().get::<ApiKeyLabel>()
+}
+
+/// The API keys accepted as `authorization: Bearer <key>` metadata, stored as
+/// the hex SHA-256 of the key along with a label naming its owner.
+///
+/// Clones share the same set, so a handle kept by the caller can
+/// [`reload`](Self::reload) the keys of a running server.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    labels: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl ApiKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the key whose hex SHA-256 is `hash`, labelling its requests
+    /// with `label`.
+    pub fn with_key_sha256(self, hash: impl AsRef<str>, label: impl Into<String>) -> Self {
+        self.labels
+            .write()
+            .expect("api keys lock poisoned")
+            .insert(hash.as_ref().to_lowercase(), label.into());
+        self
+    }
+
+    /// Replaces all keys with `keys`, a list of `(hex SHA-256, label)` pairs.
+    pub fn reload<H, L>(&self, keys: impl IntoIterator<Item = (H, L)>)
+    where
+        H: AsRef<str>,
+        L: Into<String>,
+    {
+        let labels = keys
+            .into_iter()
+            .map(|(hash, label)| (hash.as_ref().to_lowercase(), label.into()))
+            .collect();
+        *self.labels.write().expect("api keys lock poisoned") = labels;
+    }
+
+    /// Returns the label of `key` if it is accepted.
+    pub fn label_of(&self, key: &str) -> Option<String> {
+        self.labels
+            .read()
+            .expect("api keys lock poisoned")
+            .get(&sha256_hex(key.as_bytes()))
+            .cloned()
+    }
+
+    /// Checks the value of an `authorization` header.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn authenticate(&self, authorization: Option<&str>) -> Result<ApiKeyLabel, Status> {
+        let key = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing bearer token"))?;
+        let label = self
+            .label_of(key.trim())
+            .ok_or_else(|| Status::unauthenticated("invalid bearer token"))?;
+
+        log::debug!("authenticated request with api key {}", label);
+        METRICS.observe_api_key(&label);
+        Ok(ApiKeyLabel(label))
+    }
+}
+
+/// A layer requiring a valid API key in the `authorization` header of every
+/// request, and putting its [`ApiKeyLabel`] into the request extensions.
+/// Without keys configured, all requests pass through. CORS preflight
+/// requests, which browsers send without credentials, are answered before
+/// this layer.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeyLayer {
+    keys: Option<ApiKeys>,
+}
+
+impl ApiKeyLayer {
+    pub fn new(keys: Option<ApiKeys>) -> Self {
+        Self { keys }
+    }
+}
+
+impl<S> Layer<S> for ApiKeyLayer {
+    type Service = ApiKeyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiKeyService {
+            inner,
+            keys: self.keys.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ApiKeyService<S> {
+    inner: S,
+    keys: Option<ApiKeys>,
+}
+
+impl<S, ReqBody> Service<http::Request<ReqBody>> for ApiKeyService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<BoxBody>>,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<ReqBody>) -> Self::Future {
+        let keys = match &self.keys {
+            Some(keys) if !is_health_check(&req) => keys,
+            _ => return Either::Left(self.inner.call(req)),
+        };
+
+        let authorization = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        match keys.authenticate(authorization) {
+            Ok(label) => {
+                req.extensions_mut().insert(label);
+                Either::Left(self.inner.call(req))
+            }
+            Err(status) => Either::Right(future::ready(Ok(status.to_http()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn request_with_token(token: &str) -> http::Request<()> {
+        http::Request::builder()
+            .header("authorization", format!("Bearer {}", token))
+            .body(())
+            .unwrap()
+    }
+
+    /// Answers with the label the request was authenticated with in the
+    /// `label` header.
+    async fn call(layer: &ApiKeyLayer, request: http::Request<()>) -> http::Response<BoxBody> {
+        let service = layer.layer(tower::service_fn(|request: http::Request<()>| async move {
+            let mut response = http::Response::new(tonic::body::empty_body());
+            if let Some(label) = request.extensions().get::<ApiKeyLabel>() {
+                response
+                    .headers_mut()
+                    .insert("label", label.0.parse().unwrap());
+            }
+            Ok::<_, std::convert::Infallible>(response)
+        }));
+        service.oneshot(request).await.unwrap()
+    }
+
+    fn grpc_code(response: &http::Response<BoxBody>) -> Code {
+        response
+            .headers()
+            .get("grpc-status")
+            .map_or(Code::Ok, |code| Code::from_bytes(code.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn test_api_key_layer() {
+        let keys = ApiKeys::new().with_key_sha256(sha256_hex(b"secret"), "relayer-1");
+        let layer = ApiKeyLayer::new(Some(keys.clone()));
+
+        let response = call(&layer, request_with_token("secret")).await;
+        assert_eq!(response.headers()["label"], "relayer-1");
+
+        let response = call(&layer, request_with_token("other")).await;
+        assert_eq!(grpc_code(&response), Code::Unauthenticated);
+        let response = call(&layer, http::Request::new(())).await;
+        assert_eq!(grpc_code(&response), Code::Unauthenticated);
+
+        keys.reload([(sha256_hex(b"other"), "relayer-2")]);
+        let response = call(&layer, request_with_token("secret")).await;
+        assert_eq!(grpc_code(&response), Code::Unauthenticated);
+        let response = call(&layer, request_with_token("other")).await;
+        assert_eq!(response.headers()["label"], "relayer-2");
+
+        // Health checks need no key.
+        let health_check = http::Request::builder()
+            .uri("/grpc.health.v1.Health/Check")
+            .body(())
+            .unwrap();
+        let response = call(&layer, health_check).await;
+        assert_eq!(grpc_code(&response), Code::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_no_keys_configured() {
+        let response = call(&ApiKeyLayer::new(None), http::Request::new(())).await;
+        assert_eq!(grpc_code(&response), Code::Ok);
+    }
+}
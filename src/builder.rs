@@ -0,0 +1,654 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tower_http::cors::CorsLayer;
+
+use crate::auth::ApiKeys;
+use crate::cosmos::{AuthHost, SharedAuthHost, SharedStakingHost, StakingHost};
+use crate::fee::{IbcFeeStore, SharedFeeStore};
+use crate::health::HealthCheck;
+use crate::ica::{IcaStore, SharedIcaStore};
+use crate::mtls::ClientCertAllowList;
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::service::IbcGrpcService;
+use crate::tendermint::{NodeInfo, NodeVersions};
+use crate::transfer::{IbcTransferStore, SharedTransferStore};
+use crate::types::ConsistencyPolicy;
+use crate::web::CorsPolicy;
+use crate::{AsyncIbcStore, Result, ServerError};
+
+/// The validated settings `IbcGrpcService` serves with.
+#[derive(Clone, Debug)]
+pub(crate) struct ServerOptions {
+    pub(crate) addr: SocketAddr,
+    pub(crate) policy: ConsistencyPolicy,
+    pub(crate) tls: Option<ServerTlsConfig>,
+    pub(crate) client_cert_allow_list: Option<Arc<ClientCertAllowList>>,
+    pub(crate) api_keys: Option<ApiKeys>,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) metrics_addr: Option<SocketAddr>,
+    pub(crate) rest_addr: Option<SocketAddr>,
+    pub(crate) abci_addr: Option<SocketAddr>,
+    pub(crate) health_check: Option<HealthCheck>,
+    pub(crate) reflection: bool,
+    pub(crate) grpc_web: Option<CorsLayer>,
+    pub(crate) file_descriptor_sets: Vec<Vec<u8>>,
+    pub(crate) http2_keepalive_interval: Option<Duration>,
+    pub(crate) http2_keepalive_timeout: Option<Duration>,
+    pub(crate) concurrency_limit_per_connection: Option<usize>,
+    pub(crate) max_concurrent_streams: Option<u32>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) max_request_size: Option<usize>,
+    pub(crate) gzip: bool,
+    pub(crate) client_service: bool,
+    pub(crate) connection_service: bool,
+    pub(crate) channel_service: bool,
+    pub(crate) ica_store: Option<SharedIcaStore>,
+    pub(crate) transfer_store: Option<SharedTransferStore>,
+    pub(crate) fee_store: Option<SharedFeeStore>,
+    pub(crate) node_info: Option<NodeInfo>,
+    pub(crate) staking_host: Option<SharedStakingHost>,
+    pub(crate) auth_host: Option<SharedAuthHost>,
+}
+
+/// Configures and builds an [`IbcGrpcService`]. Every setting but the listen
+/// address is optional; by default all three query services are mounted,
+/// without TLS or limits.
+#[derive(Clone, Debug)]
+pub struct IbcGrpcServiceBuilder {
+    addr: String,
+    policy: ConsistencyPolicy,
+    tls_pem_files: Option<(PathBuf, PathBuf)>,
+    tls_client_ca_pem_file: Option<PathBuf>,
+    client_cert_allow_list: Option<ClientCertAllowList>,
+    api_keys: Option<ApiKeys>,
+    rate_limit: Option<RateLimit>,
+    metrics_addr: Option<String>,
+    rest_addr: Option<String>,
+    abci_addr: Option<String>,
+    health_check: Option<HealthCheck>,
+    reflection: bool,
+    grpc_web: Option<CorsPolicy>,
+    file_descriptor_sets: Vec<Vec<u8>>,
+    http2_keepalive_interval: Option<Duration>,
+    http2_keepalive_timeout: Option<Duration>,
+    concurrency_limit_per_connection: Option<usize>,
+    max_concurrent_streams: Option<u32>,
+    timeout: Option<Duration>,
+    max_request_size: Option<usize>,
+    gzip: bool,
+    client_service: bool,
+    connection_service: bool,
+    channel_service: bool,
+    ica_store: Option<SharedIcaStore>,
+    transfer_store: Option<SharedTransferStore>,
+    fee_store: Option<SharedFeeStore>,
+    node_info: Option<NodeInfo>,
+    node_versions: NodeVersions,
+    staking_host: Option<SharedStakingHost>,
+    auth_host: Option<SharedAuthHost>,
+}
+
+impl IbcGrpcServiceBuilder {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            policy: ConsistencyPolicy::default(),
+            tls_pem_files: None,
+            tls_client_ca_pem_file: None,
+            client_cert_allow_list: None,
+            api_keys: None,
+            rate_limit: None,
+            metrics_addr: None,
+            rest_addr: None,
+            abci_addr: None,
+            health_check: None,
+            reflection: false,
+            grpc_web: None,
+            file_descriptor_sets: vec![],
+            http2_keepalive_interval: None,
+            http2_keepalive_timeout: None,
+            concurrency_limit_per_connection: None,
+            max_concurrent_streams: None,
+            timeout: None,
+            max_request_size: None,
+            gzip: false,
+            client_service: true,
+            connection_service: true,
+            channel_service: true,
+            ica_store: None,
+            transfer_store: None,
+            fee_store: None,
+            node_info: None,
+            node_versions: NodeVersions::default(),
+            staking_host: None,
+            auth_host: None,
+        }
+    }
+
+    pub fn consistency_policy(mut self, policy: ConsistencyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Serves over TLS with the PEM encoded certificate chain and private key
+    /// read from the given files.
+    pub fn tls_pem_files(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.tls_pem_files = Some((cert.into(), key.into()));
+        self
+    }
+
+    /// Requires clients to present a certificate issued by one of the PEM
+    /// encoded CA certificates read from the given file.
+    pub fn tls_client_ca_pem_file(mut self, ca: impl Into<PathBuf>) -> Self {
+        self.tls_client_ca_pem_file = Some(ca.into());
+        self
+    }
+
+    /// Only serves the mutual TLS clients whose certificate is on
+    /// `allow_list`.
+    pub fn client_cert_allow_list(mut self, allow_list: ClientCertAllowList) -> Self {
+        self.client_cert_allow_list = Some(allow_list);
+        self
+    }
+
+    /// Requires an `authorization: Bearer <key>` metadata entry holding one
+    /// of `keys` on every request. Keep a clone of `keys` to reload them while
+    /// the server runs.
+    pub fn api_keys(mut self, keys: ApiKeys) -> Self {
+        self.api_keys = Some(keys);
+        self
+    }
+
+    /// Limits the request rate of every client, identified by its API key or
+    /// its IP address.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Serves Prometheus metrics on `http://<addr>/metrics` while the server
+    /// runs.
+    pub fn metrics_addr(mut self, addr: impl Into<String>) -> Self {
+        self.metrics_addr = Some(addr.into());
+        self
+    }
+
+    /// Serves the enabled queries as JSON on the Cosmos SDK REST routes, e.g.
+    /// `GET http://<addr>/ibc/core/channel/v1/channels`, while the server
+    /// runs. API keys and rate limits apply as they do to gRPC requests, but
+    /// the gateway serves plain HTTP, so it cannot be combined with a client
+    /// CA. List queries return every entry and no `pagination` in the
+    /// response.
+    pub fn rest_gateway_addr(mut self, addr: impl Into<String>) -> Self {
+        self.rest_addr = Some(addr.into());
+        self
+    }
+
+    /// Answers Tendermint `abci_query` JSON-RPC calls for `store/ibc/key` on
+    /// `POST http://<addr>/`, from the raw values and proofs of the store,
+    /// while the server runs. The endpoint has no authentication, so it
+    /// cannot be combined with a client CA or API keys.
+    pub fn abci_query_addr(mut self, addr: impl Into<String>) -> Self {
+        self.abci_addr = Some(addr.into());
+        self
+    }
+
+    /// Derives the status of the `grpc.health.v1.Health` service from the
+    /// store. Without a check, the health service always reports `SERVING`.
+    /// Health checks need no API key and are not rate limited, but with a
+    /// client CA, probes must present a certificate like any other client.
+    pub fn health_check(mut self, check: HealthCheck) -> Self {
+        self.health_check = Some(check);
+        self
+    }
+
+    /// Registers the `grpc.reflection.v1alpha.ServerReflection` service. It
+    /// describes every mounted query service out of the box.
+    pub fn reflection(mut self, enabled: bool) -> Self {
+        self.reflection = enabled;
+        self
+    }
+
+    /// Makes the services described by the encoded `FileDescriptorSet` known
+    /// to reflection, and enables it, e.g. the set of the application
+    /// queries built with `buf build proto --as-file-descriptor-set -o app.bin`.
+    pub fn reflection_file_descriptor_set(mut self, set: impl Into<Vec<u8>>) -> Self {
+        self.reflection = true;
+        self.file_descriptor_sets.push(set.into());
+        self
+    }
+
+    /// Also answers gRPC-Web requests to the mounted services, from the
+    /// origins allowed by `cors`. Authentication and rate limit failures
+    /// carry the CORS headers too, so browser apps can read them.
+    pub fn grpc_web(mut self, cors: CorsPolicy) -> Self {
+        self.grpc_web = Some(cors);
+        self
+    }
+
+    pub fn http2_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.http2_keepalive_interval = Some(interval);
+        self
+    }
+
+    pub fn http2_keepalive_timeout(mut self, timeout: Duration) -> Self {
+        self.http2_keepalive_timeout = Some(timeout);
+        self
+    }
+
+    pub fn concurrency_limit_per_connection(mut self, limit: usize) -> Self {
+        self.concurrency_limit_per_connection = Some(limit);
+        self
+    }
+
+    pub fn max_concurrent_streams(mut self, max: u32) -> Self {
+        self.max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// Fails requests that are not answered within `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Rejects requests whose encoded message is larger than `size` bytes with
+    /// `RESOURCE_EXHAUSTED`.
+    pub fn max_request_size(mut self, size: usize) -> Self {
+        self.max_request_size = Some(size);
+        self
+    }
+
+    /// Accepts gzip compressed requests and compresses responses with gzip
+    /// when the client accepts it.
+    pub fn gzip(mut self, enabled: bool) -> Self {
+        self.gzip = enabled;
+        self
+    }
+
+    pub fn client_service(mut self, enabled: bool) -> Self {
+        self.client_service = enabled;
+        self
+    }
+
+    pub fn connection_service(mut self, enabled: bool) -> Self {
+        self.connection_service = enabled;
+        self
+    }
+
+    pub fn channel_service(mut self, enabled: bool) -> Self {
+        self.channel_service = enabled;
+        self
+    }
+
+    /// Mounts the ICS-20 `ibc.applications.transfer.v1.Query` service, served
+    /// by `store`.
+    pub fn transfer_store(mut self, store: impl IbcTransferStore + 'static) -> Self {
+        self.transfer_store = Some(SharedTransferStore(Arc::new(store)));
+        self
+    }
+
+    /// Mounts the ICS-27 interchain accounts controller and host query
+    /// services, served by `store`.
+    pub fn ica_store(mut self, store: impl IcaStore + 'static) -> Self {
+        self.ica_store = Some(SharedIcaStore(Arc::new(store)));
+        self
+    }
+
+    /// Mounts the ICS-29 `ibc.applications.fee.v1.Query` service, served by
+    /// `store`.
+    pub fn fee_store(mut self, store: impl IbcFeeStore + 'static) -> Self {
+        self.fee_store = Some(SharedFeeStore(Arc::new(store)));
+        self
+    }
+
+    /// Mounts a minimal `cosmos.base.tendermint.v1beta1.Service` reporting
+    /// `chain_id`, the application `version` and the current height of the
+    /// store, for relayers that query it before the IBC services. Off by
+    /// default.
+    pub fn tendermint_service(
+        mut self,
+        chain_id: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Self {
+        self.node_info = Some(NodeInfo {
+            chain_id: chain_id.into(),
+            version: version.into(),
+            versions: NodeVersions::default(),
+        });
+        self
+    }
+
+    /// The Tendermint version the tendermint service reports as the node
+    /// version, `0.34.21` by default.
+    pub fn tendermint_node_version(mut self, version: impl Into<String>) -> Self {
+        self.node_versions.tendermint = version.into();
+        self
+    }
+
+    /// The Cosmos SDK version the tendermint service reports, `v0.46.1` by
+    /// default. Relayers check it for compatibility.
+    pub fn cosmos_sdk_version(mut self, version: impl Into<String>) -> Self {
+        self.node_versions.cosmos_sdk = version.into();
+        self
+    }
+
+    /// The ibc-go version the tendermint service reports among the build
+    /// dependencies, `v5.0.0` by default. Relayers check it for
+    /// compatibility.
+    pub fn ibc_go_version(mut self, version: impl Into<String>) -> Self {
+        self.node_versions.ibc_go = version.into();
+        self
+    }
+
+    /// Mounts `cosmos.staking.v1beta1.Query` answering `Params` from `host`,
+    /// e.g. a fixed [`StakingParams`](crate::StakingParams).
+    pub fn staking_host(mut self, host: impl StakingHost + 'static) -> Self {
+        self.staking_host = Some(SharedStakingHost(Arc::new(host)));
+        self
+    }
+
+    /// Mounts `cosmos.auth.v1beta1.Query` answering `Account` from `host`,
+    /// e.g. a fixed list of [`Account`](crate::Account)s.
+    pub fn auth_host(mut self, host: impl AuthHost + 'static) -> Self {
+        self.auth_host = Some(SharedAuthHost(Arc::new(host)));
+        self
+    }
+
+    pub fn build<Store>(self, store: Store) -> Result<IbcGrpcService<Store>>
+    where
+        Store: AsyncIbcStore + 'static,
+    {
+        Ok(IbcGrpcService::with_options(store, self.into_options()?))
+    }
+
+    fn into_options(self) -> Result<ServerOptions> {
+        let addr = parse_addr("listen", &self.addr)?;
+
+        if self.client_cert_allow_list.is_some() && self.tls_client_ca_pem_file.is_none() {
+            return Err(ServerError::Config(
+                "a client certificate allow-list requires a client CA".to_owned(),
+            ));
+        }
+        if self.rest_addr.is_some() && self.tls_client_ca_pem_file.is_some() {
+            return Err(ServerError::Config(
+                "the REST gateway serves plain HTTP and cannot verify client certificates"
+                    .to_owned(),
+            ));
+        }
+
+        if self.abci_addr.is_some()
+            && (self.tls_client_ca_pem_file.is_some() || self.api_keys.is_some())
+        {
+            return Err(ServerError::Config(
+                "the ABCI query endpoint cannot authenticate clients".to_owned(),
+            ));
+        }
+
+        let tls = match (self.tls_pem_files, self.tls_client_ca_pem_file) {
+            (Some((cert_path, key_path)), ca_path) => {
+                let cert = read_pem(&cert_path)?;
+                let key = read_pem(&key_path)?;
+                let tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+                match ca_path {
+                    Some(ca_path) => {
+                        Some(tls.client_ca_root(Certificate::from_pem(read_pem(&ca_path)?)))
+                    }
+                    None => Some(tls),
+                }
+            }
+            (None, Some(_)) => {
+                return Err(ServerError::Config(
+                    "a client CA requires a server certificate".to_owned(),
+                ))
+            }
+            (None, None) => None,
+        };
+
+        let metrics_addr = self
+            .metrics_addr
+            .as_deref()
+            .map(|addr| parse_addr("metrics", addr))
+            .transpose()?;
+        let rest_addr = self
+            .rest_addr
+            .as_deref()
+            .map(|addr| parse_addr("REST gateway", addr))
+            .transpose()?;
+        let abci_addr = self
+            .abci_addr
+            .as_deref()
+            .map(|addr| parse_addr("ABCI query", addr))
+            .transpose()?;
+
+        if let Some(limit) = &self.rate_limit {
+            if limit.capacity == 0
+                || limit.refill_per_second.is_nan()
+                || limit.refill_per_second <= 0.0
+            {
+                return Err(ServerError::Config(
+                    "rate limit capacity and refill rate must be positive".to_owned(),
+                ));
+            }
+            if limit.scan_cost > limit.capacity {
+                return Err(ServerError::Config(format!(
+                    "rate limit scan cost {} exceeds the capacity {}",
+                    limit.scan_cost, limit.capacity
+                )));
+            }
+        }
+
+        if let Some(check) = &self.health_check {
+            if check.probe_interval.is_zero() {
+                return Err(ServerError::Config(
+                    "health probe interval must be positive".to_owned(),
+                ));
+            }
+        }
+
+        let grpc_web = self
+            .grpc_web
+            .as_ref()
+            .map(CorsPolicy::to_cors_layer)
+            .transpose()?;
+
+        if let Some(node_info) = &self.node_info {
+            if node_info.chain_id.trim().is_empty() {
+                return Err(ServerError::Config("empty chain id".to_owned()));
+            }
+        }
+
+        if !(self.client_service
+            || self.connection_service
+            || self.channel_service
+            || self.transfer_store.is_some()
+            || self.ica_store.is_some()
+            || self.fee_store.is_some()
+            || self.node_info.is_some()
+            || self.staking_host.is_some()
+            || self.auth_host.is_some())
+        {
+            return Err(ServerError::Config("no service enabled".to_owned()));
+        }
+
+        Ok(ServerOptions {
+            addr,
+            policy: self.policy,
+            tls,
+            client_cert_allow_list: self.client_cert_allow_list.map(Arc::new),
+            api_keys: self.api_keys,
+            rate_limiter: self
+                .rate_limit
+                .map(|limit| Arc::new(RateLimiter::new(limit))),
+            metrics_addr,
+            rest_addr,
+            abci_addr,
+            health_check: self.health_check,
+            reflection: self.reflection,
+            grpc_web,
+            file_descriptor_sets: self.file_descriptor_sets,
+            http2_keepalive_interval: self.http2_keepalive_interval,
+            http2_keepalive_timeout: self.http2_keepalive_timeout,
+            concurrency_limit_per_connection: self.concurrency_limit_per_connection,
+            max_concurrent_streams: self.max_concurrent_streams,
+            timeout: self.timeout,
+            max_request_size: self.max_request_size,
+            gzip: self.gzip,
+            client_service: self.client_service,
+            connection_service: self.connection_service,
+            channel_service: self.channel_service,
+            transfer_store: self.transfer_store,
+            ica_store: self.ica_store,
+            fee_store: self.fee_store,
+            node_info: self.node_info.map(|node_info| NodeInfo {
+                versions: self.node_versions,
+                ..node_info
+            }),
+            staking_host: self.staking_host,
+            auth_host: self.auth_host,
+        })
+    }
+}
+
+fn parse_addr(kind: &str, addr: &str) -> Result<SocketAddr> {
+    addr.parse()
+        .map_err(|e| ServerError::Config(format!("invalid {} address {:?}: {}", kind, addr, e)))
+}
+
+fn read_pem(path: &PathBuf) -> Result<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| ServerError::Config(format!("failed to read {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_addr() {
+        let err = IbcGrpcServiceBuilder::new("localhost")
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_missing_tls_files() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .tls_pem_files("/nonexistent/cert.pem", "/nonexistent/key.pem")
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_allow_list_requires_client_ca() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .client_cert_allow_list(ClientCertAllowList::new().allow_subject("CN=relayer"))
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_invalid_rest_gateway_addr() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .rest_gateway_addr("localhost")
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_rest_gateway_rejects_client_ca() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .tls_pem_files("/nonexistent/cert.pem", "/nonexistent/key.pem")
+            .tls_client_ca_pem_file("/nonexistent/ca.pem")
+            .rest_gateway_addr("127.0.0.1:1317")
+            .into_options()
+            .unwrap_err();
+        assert!(err.to_string().contains("REST gateway"));
+    }
+
+    #[test]
+    fn test_abci_query_rejects_authentication() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .api_keys(ApiKeys::new().with_key_sha256("ab".repeat(32), "relayer"))
+            .abci_query_addr("127.0.0.1:26657")
+            .into_options()
+            .unwrap_err();
+        assert!(err.to_string().contains("ABCI query"));
+
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .tls_pem_files("/nonexistent/cert.pem", "/nonexistent/key.pem")
+            .tls_client_ca_pem_file("/nonexistent/ca.pem")
+            .abci_query_addr("127.0.0.1:26657")
+            .into_options()
+            .unwrap_err();
+        assert!(err.to_string().contains("ABCI query"));
+    }
+
+    #[test]
+    fn test_invalid_rate_limit() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .rate_limit(RateLimit::new(5, 1.0).scan_cost(10))
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_invalid_cors_origin() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .grpc_web(CorsPolicy::new().allow_origin("https://explorer.example\n"))
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_empty_chain_id() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .tendermint_service(" ", "0.1.0")
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_no_service_enabled() {
+        let err = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .client_service(false)
+            .connection_service(false)
+            .channel_service(false)
+            .into_options()
+            .unwrap_err();
+        assert!(matches!(err, ServerError::Config(_)));
+    }
+
+    #[test]
+    fn test_application_service_only() {
+        let options = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .client_service(false)
+            .connection_service(false)
+            .channel_service(false)
+            .staking_host(crate::StakingParams::default())
+            .into_options()
+            .unwrap();
+        assert!(options.staking_host.is_some() && options.ica_store.is_none());
+    }
+
+    #[test]
+    fn test_default_options() {
+        let options = IbcGrpcServiceBuilder::new("127.0.0.1:9090")
+            .into_options()
+            .unwrap();
+        assert_eq!(options.addr, "127.0.0.1:9090".parse().unwrap());
+        assert!(options.tls.is_none());
+        assert!(options.client_service && options.connection_service && options.channel_service);
+    }
+}
@@ -0,0 +1,225 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::task::{Context, Poll};
+
+use futures::future::{self, BoxFuture};
+use http::{Request, Response};
+use tonic::{body::BoxBody, Status};
+use tower::{Layer, Service};
+
+use crate::metrics::METRICS;
+
+static INSTALL_LOCATION_HOOK: Once = Once::new();
+
+thread_local! {
+    static PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Chains a panic hook recording where the last panic of the current thread
+/// happened, as the payload caught by `catch_unwind` does not carry it.
+pub(crate) fn install_location_hook() {
+    INSTALL_LOCATION_HOOK.call_once(|| {
+        let prev_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info.location().map(|location| location.to_string());
+            PANIC_LOCATION.with(|cell| *cell.borrow_mut() = location);
+            prev_hook(info);
+        }));
+    });
+}
+
+/// A panic caught by [`catch_unwind`].
+pub(crate) struct Panic {
+    payload: Box<dyn Any + Send>,
+    /// Where the panic happened, if the hook ran for it on the catching
+    /// thread.
+    location: Option<String>,
+}
+
+/// Runs `f`, catching a panic with the location the hook recorded for it. The
+/// location is cleared first, so that a panic re-raised with `resume_unwind`,
+/// which skips the hook, reports none rather than that of an earlier panic,
+/// unless it was re-raised by [`spawn_blocking`] with its own.
+fn catch_with_location<T>(f: impl FnOnce() -> T) -> Result<T, Panic> {
+    PANIC_LOCATION.with(|cell| cell.borrow_mut().take());
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let location = PANIC_LOCATION.with(|cell| cell.borrow_mut().take());
+        match payload.downcast::<Panic>() {
+            Ok(panic) => *panic,
+            Err(payload) => Panic { payload, location },
+        }
+    })
+}
+
+/// Runs `f` on the blocking pool, re-raising a panic of it on the calling
+/// task as a direct call would have done. The hook records the location on
+/// the blocking thread, so the re-raised panic carries it along.
+pub(crate) async fn spawn_blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(move || catch_with_location(f)).await {
+        Ok(Ok(ret)) => ret,
+        Ok(Err(panic)) => panic::resume_unwind(Box::new(panic)),
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Awaits `future`, catching a panic of any of its polls.
+pub(crate) async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, Panic> {
+    futures::pin_mut!(future);
+    future::poll_fn(
+        |cx| match catch_with_location(|| future.as_mut().poll(cx)) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
+
+/// A layer turning panics of the wrapped gRPC services into statuses, so that
+/// a panicking handler fails its own request instead of the whole connection.
+/// `unimplemented!()` and `todo!()` become `UNIMPLEMENTED`, any other panic
+/// becomes `INTERNAL`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CatchPanicLayer;
+
+impl<S> Layer<S> for CatchPanicLayer {
+    type Service = CatchPanic<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        install_location_hook();
+        CatchPanic { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CatchPanic<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for CatchPanic<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let method = req.uri().path().to_owned();
+
+        let future = match catch_with_location(|| self.inner.call(req)) {
+            Ok(future) => future,
+            Err(panic) => return Box::pin(async move { Ok(panic_response(&method, panic)) }),
+        };
+
+        Box::pin(async move {
+            match catch_unwind(future).await {
+                Ok(res) => res,
+                Err(panic) => Ok(panic_response(&method, panic)),
+            }
+        })
+    }
+}
+
+fn panic_response(method: &str, panic: Panic) -> Response<BoxBody> {
+    panic_status(method, panic).to_http()
+}
+
+/// Counts and logs a panic of the handler of `method`, and turns it into the
+/// status the request fails with.
+pub(crate) fn panic_status(method: &str, panic: Panic) -> Status {
+    METRICS.observe_caught_panic();
+
+    let message = panic
+        .payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| panic.payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_owned());
+    let location = panic
+        .location
+        .unwrap_or_else(|| "unknown location".to_owned());
+    log::error!("{} panicked at {}: {}", method, location, message);
+
+    // The messages of `unimplemented!()` and `todo!()`.
+    if message.starts_with("not implemented") || message.starts_with("not yet implemented") {
+        Status::unimplemented(format!("{} is not implemented", method))
+    } else {
+        Status::internal(format!("{} panicked", method))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tonic::Code;
+
+    use super::*;
+
+    async fn unimplemented_handler(_req: Request<()>) -> Result<Response<BoxBody>, Infallible> {
+        unimplemented!()
+    }
+
+    async fn panicking_handler(_req: Request<()>) -> Result<Response<BoxBody>, Infallible> {
+        panic!("inconsistent store")
+    }
+
+    async fn status_of<S>(mut svc: S) -> Status
+    where
+        S: Service<Request<()>, Response = Response<BoxBody>, Error = Infallible>,
+    {
+        let res = svc.call(Request::new(())).await.unwrap();
+        Status::from_header_map(res.headers()).unwrap()
+    }
+
+    #[test]
+    fn test_panic_location() {
+        install_location_hook();
+        let panic = catch_with_location(|| panic!("inconsistent store")).unwrap_err();
+        assert!(panic.location.unwrap().starts_with(file!()));
+
+        // The hook does not run for a re-raised panic, so there is no location
+        // rather than that of the panic above.
+        let panic =
+            catch_with_location(|| panic::resume_unwind(Box::new("re-raised"))).unwrap_err();
+        assert_eq!(panic.location, None);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_panic_location() {
+        install_location_hook();
+        let panic = catch_unwind(spawn_blocking(|| panic!("inconsistent store")))
+            .await
+            .unwrap_err();
+        assert!(panic.location.unwrap().starts_with(file!()));
+        assert_eq!(
+            panic.payload.downcast_ref::<&str>(),
+            Some(&"inconsistent store")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unimplemented_panic_becomes_unimplemented() {
+        let svc = CatchPanicLayer.layer(tower::service_fn(unimplemented_handler));
+        assert_eq!(status_of(svc).await.code(), Code::Unimplemented);
+    }
+
+    #[tokio::test]
+    async fn test_panic_becomes_internal() {
+        let svc = CatchPanicLayer.layer(tower::service_fn(panicking_handler));
+        assert_eq!(status_of(svc).await.code(), Code::Internal);
+    }
+}
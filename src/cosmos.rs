@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use ibc_proto::cosmos::auth::v1beta1::{
+    query_server::Query as AuthQuery, AddressBytesToStringRequest, AddressBytesToStringResponse,
+    AddressStringToBytesRequest, AddressStringToBytesResponse, BaseAccount, Bech32PrefixRequest,
+    Bech32PrefixResponse, QueryAccountAddressByIdRequest, QueryAccountAddressByIdResponse,
+    QueryAccountRequest, QueryAccountResponse, QueryAccountsRequest, QueryAccountsResponse,
+    QueryModuleAccountsRequest, QueryModuleAccountsResponse,
+    QueryParamsRequest as AuthParamsRequest, QueryParamsResponse as AuthParamsResponse,
+};
+use ibc_proto::cosmos::staking::v1beta1::{
+    query_server::Query as StakingQuery, Params as RawStakingParams, QueryDelegationRequest,
+    QueryDelegationResponse, QueryDelegatorDelegationsRequest, QueryDelegatorDelegationsResponse,
+    QueryDelegatorUnbondingDelegationsRequest, QueryDelegatorUnbondingDelegationsResponse,
+    QueryDelegatorValidatorRequest, QueryDelegatorValidatorResponse,
+    QueryDelegatorValidatorsRequest, QueryDelegatorValidatorsResponse, QueryHistoricalInfoRequest,
+    QueryHistoricalInfoResponse, QueryParamsRequest as StakingParamsRequest,
+    QueryParamsResponse as StakingParamsResponse, QueryPoolRequest, QueryPoolResponse,
+    QueryRedelegationsRequest, QueryRedelegationsResponse, QueryUnbondingDelegationRequest,
+    QueryUnbondingDelegationResponse, QueryValidatorDelegationsRequest,
+    QueryValidatorDelegationsResponse, QueryValidatorRequest, QueryValidatorResponse,
+    QueryValidatorUnbondingDelegationsRequest, QueryValidatorUnbondingDelegationsResponse,
+    QueryValidatorsRequest, QueryValidatorsResponse,
+};
+use ibc_proto::google::protobuf::{Any, Duration as RawDuration};
+use prost::Message;
+use tonic::{Request, Response, Status};
+
+use crate::instrument::timed_blocking;
+use crate::types::{Account, StakingParams};
+use crate::{Result, ServerError};
+
+const BASE_ACCOUNT_TYPE_URL: &str = "/cosmos.auth.v1beta1.BaseAccount";
+
+/// Reports the staking parameters of the host to the staking query service,
+/// which relayers health-check counterparties with. A fixed
+/// [`StakingParams`] serves as static configuration. Calls run on the
+/// blocking pool.
+pub trait StakingHost: Sync + Send {
+    fn get_staking_params(&self) -> Result<StakingParams>;
+}
+
+impl StakingHost for StakingParams {
+    fn get_staking_params(&self) -> Result<StakingParams> {
+        Ok(self.clone())
+    }
+}
+
+/// Looks up the accounts of the host for the auth query service, which
+/// relayers read their account sequence from. A fixed list of [`Account`]s
+/// serves as static configuration. Calls run on the blocking pool.
+pub trait AuthHost: Sync + Send {
+    fn get_account(&self, address: &str) -> Result<Option<Account>>;
+}
+
+impl AuthHost for Vec<Account> {
+    fn get_account(&self, address: &str) -> Result<Option<Account>> {
+        Ok(self
+            .iter()
+            .find(|account| account.address == address)
+            .cloned())
+    }
+}
+
+/// A staking host shared by the staking query services.
+#[derive(Clone)]
+pub(crate) struct SharedStakingHost(pub(crate) Arc<dyn StakingHost>);
+
+impl std::fmt::Debug for SharedStakingHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedStakingHost")
+    }
+}
+
+/// An auth host shared by the auth query services.
+#[derive(Clone)]
+pub(crate) struct SharedAuthHost(pub(crate) Arc<dyn AuthHost>);
+
+impl std::fmt::Debug for SharedAuthHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedAuthHost")
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn unimplemented<T>(what: &str) -> std::result::Result<Response<T>, Status> {
+    Err(ServerError::Unimplemented(what.to_owned()).into())
+}
+
+fn raw_staking_params(params: StakingParams) -> RawStakingParams {
+    RawStakingParams {
+        unbonding_time: Some(RawDuration {
+            seconds: i64::try_from(params.unbonding_time.as_secs()).unwrap_or(i64::MAX),
+            nanos: params.unbonding_time.subsec_nanos() as i32,
+        }),
+        bond_denom: params.bond_denom,
+        ..Default::default()
+    }
+}
+
+fn account_to_any(account: Account) -> Any {
+    Any {
+        type_url: BASE_ACCOUNT_TYPE_URL.to_owned(),
+        value: BaseAccount {
+            address: account.address,
+            pub_key: None,
+            account_number: account.account_number,
+            sequence: account.sequence,
+        }
+        .encode_to_vec(),
+    }
+}
+
+/// Serves the `Params` query of `cosmos.staking.v1beta1.Query` from a
+/// [`StakingHost`]. The validator and delegation queries are not
+/// implemented.
+pub struct IbcStakingService {
+    host: Arc<dyn StakingHost>,
+}
+
+impl IbcStakingService {
+    pub(crate) fn new(host: SharedStakingHost) -> Self {
+        Self { host: host.0 }
+    }
+}
+
+#[tonic::async_trait]
+impl StakingQuery for IbcStakingService {
+    async fn validators(
+        &self,
+        _request: Request<QueryValidatorsRequest>,
+    ) -> std::result::Result<Response<QueryValidatorsResponse>, Status> {
+        unimplemented("validators")
+    }
+
+    async fn validator(
+        &self,
+        _request: Request<QueryValidatorRequest>,
+    ) -> std::result::Result<Response<QueryValidatorResponse>, Status> {
+        unimplemented("validators")
+    }
+
+    async fn validator_delegations(
+        &self,
+        _request: Request<QueryValidatorDelegationsRequest>,
+    ) -> std::result::Result<Response<QueryValidatorDelegationsResponse>, Status> {
+        unimplemented("delegations")
+    }
+
+    async fn validator_unbonding_delegations(
+        &self,
+        _request: Request<QueryValidatorUnbondingDelegationsRequest>,
+    ) -> std::result::Result<Response<QueryValidatorUnbondingDelegationsResponse>, Status> {
+        unimplemented("unbonding delegations")
+    }
+
+    async fn delegation(
+        &self,
+        _request: Request<QueryDelegationRequest>,
+    ) -> std::result::Result<Response<QueryDelegationResponse>, Status> {
+        unimplemented("delegations")
+    }
+
+    async fn unbonding_delegation(
+        &self,
+        _request: Request<QueryUnbondingDelegationRequest>,
+    ) -> std::result::Result<Response<QueryUnbondingDelegationResponse>, Status> {
+        unimplemented("unbonding delegations")
+    }
+
+    async fn delegator_delegations(
+        &self,
+        _request: Request<QueryDelegatorDelegationsRequest>,
+    ) -> std::result::Result<Response<QueryDelegatorDelegationsResponse>, Status> {
+        unimplemented("delegations")
+    }
+
+    async fn delegator_unbonding_delegations(
+        &self,
+        _request: Request<QueryDelegatorUnbondingDelegationsRequest>,
+    ) -> std::result::Result<Response<QueryDelegatorUnbondingDelegationsResponse>, Status> {
+        unimplemented("unbonding delegations")
+    }
+
+    async fn redelegations(
+        &self,
+        _request: Request<QueryRedelegationsRequest>,
+    ) -> std::result::Result<Response<QueryRedelegationsResponse>, Status> {
+        unimplemented("redelegations")
+    }
+
+    async fn delegator_validators(
+        &self,
+        _request: Request<QueryDelegatorValidatorsRequest>,
+    ) -> std::result::Result<Response<QueryDelegatorValidatorsResponse>, Status> {
+        unimplemented("validators")
+    }
+
+    async fn delegator_validator(
+        &self,
+        _request: Request<QueryDelegatorValidatorRequest>,
+    ) -> std::result::Result<Response<QueryDelegatorValidatorResponse>, Status> {
+        unimplemented("validators")
+    }
+
+    async fn historical_info(
+        &self,
+        _request: Request<QueryHistoricalInfoRequest>,
+    ) -> std::result::Result<Response<QueryHistoricalInfoResponse>, Status> {
+        unimplemented("historical info")
+    }
+
+    async fn pool(
+        &self,
+        _request: Request<QueryPoolRequest>,
+    ) -> std::result::Result<Response<QueryPoolResponse>, Status> {
+        unimplemented("staking pool")
+    }
+
+    async fn params(
+        &self,
+        _request: Request<StakingParamsRequest>,
+    ) -> std::result::Result<Response<StakingParamsResponse>, Status> {
+        let params = timed_blocking(&self.host, "get_staking_params", |host| {
+            host.get_staking_params()
+        })
+        .await?;
+        Ok(Response::new(StakingParamsResponse {
+            params: Some(raw_staking_params(params)),
+        }))
+    }
+}
+
+/// Serves the `Account` query of `cosmos.auth.v1beta1.Query` from an
+/// [`AuthHost`], answering with `BaseAccount`s. The other queries are not
+/// implemented.
+pub struct IbcAuthService {
+    host: Arc<dyn AuthHost>,
+}
+
+impl IbcAuthService {
+    pub(crate) fn new(host: SharedAuthHost) -> Self {
+        Self { host: host.0 }
+    }
+}
+
+#[tonic::async_trait]
+impl AuthQuery for IbcAuthService {
+    async fn accounts(
+        &self,
+        _request: Request<QueryAccountsRequest>,
+    ) -> std::result::Result<Response<QueryAccountsResponse>, Status> {
+        unimplemented("account listing")
+    }
+
+    async fn account(
+        &self,
+        request: Request<QueryAccountRequest>,
+    ) -> std::result::Result<Response<QueryAccountResponse>, Status> {
+        let address = request.get_ref().address.trim().to_owned();
+        if address.is_empty() {
+            return Err(ServerError::InvalidArgument("empty address".to_owned())
+                .into_status_with([("field", "address".to_owned())]));
+        }
+
+        let not_found = format!("account {}", address);
+        let account = timed_blocking(&self.host, "get_account", move |host| {
+            host.get_account(&address)
+        })
+        .await?
+        .ok_or(ServerError::NotFound(not_found))?;
+        Ok(Response::new(QueryAccountResponse {
+            account: Some(account_to_any(account)),
+        }))
+    }
+
+    async fn account_address_by_id(
+        &self,
+        _request: Request<QueryAccountAddressByIdRequest>,
+    ) -> std::result::Result<Response<QueryAccountAddressByIdResponse>, Status> {
+        unimplemented("account numbers")
+    }
+
+    async fn params(
+        &self,
+        _request: Request<AuthParamsRequest>,
+    ) -> std::result::Result<Response<AuthParamsResponse>, Status> {
+        unimplemented("auth params")
+    }
+
+    async fn module_accounts(
+        &self,
+        _request: Request<QueryModuleAccountsRequest>,
+    ) -> std::result::Result<Response<QueryModuleAccountsResponse>, Status> {
+        unimplemented("module accounts")
+    }
+
+    async fn bech32_prefix(
+        &self,
+        _request: Request<Bech32PrefixRequest>,
+    ) -> std::result::Result<Response<Bech32PrefixResponse>, Status> {
+        unimplemented("bech32 prefix")
+    }
+
+    async fn address_bytes_to_string(
+        &self,
+        _request: Request<AddressBytesToStringRequest>,
+    ) -> std::result::Result<Response<AddressBytesToStringResponse>, Status> {
+        unimplemented("address conversion")
+    }
+
+    async fn address_string_to_bytes(
+        &self,
+        _request: Request<AddressStringToBytesRequest>,
+    ) -> std::result::Result<Response<AddressStringToBytesResponse>, Status> {
+        unimplemented("address conversion")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_raw_staking_params() {
+        let params = raw_staking_params(StakingParams {
+            unbonding_time: Duration::from_millis(1_814_400_500),
+            bond_denom: "stake".to_owned(),
+        });
+        let unbonding_time = params.unbonding_time.unwrap();
+        assert_eq!(unbonding_time.seconds, 1_814_400);
+        assert_eq!(unbonding_time.nanos, 500_000_000);
+        assert_eq!(params.bond_denom, "stake");
+    }
+
+    #[test]
+    fn test_account_to_any() {
+        let account = Account {
+            address: "cosmos1relayer".to_owned(),
+            account_number: 7,
+            sequence: 42,
+        };
+        let any = account_to_any(account);
+        assert_eq!(any.type_url, BASE_ACCOUNT_TYPE_URL);
+        let decoded = BaseAccount::decode(any.value.as_slice()).unwrap();
+        assert_eq!(decoded.sequence, 42);
+        assert_eq!(decoded.account_number, 7);
+    }
+}
@@ -1,12 +1,194 @@
+use std::collections::HashMap;
 use std::str::Utf8Error;
+use std::time::Duration;
 
 use derive_more::Display;
 use ibc::core::ics24_host::error::ValidationError;
+use ibc_proto::google::protobuf::Any;
+use prost::Message;
+use tonic::{Code, Status};
+
+/// The `domain` of the `google.rpc.ErrorInfo` attached to every failure.
+pub const ERROR_DOMAIN: &str = "ibc-grpc-server";
+
+const ERROR_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.ErrorInfo";
+const RETRY_INFO_TYPE_URL: &str = "type.googleapis.com/google.rpc.RetryInfo";
 
 #[derive(Debug, Display)]
 pub enum ServerError {
     ValidateIdentifier(ValidationError),
     FromUtf8(Utf8Error),
+    /// A request parameter is malformed, e.g. a non-numeric sequence.
+    #[display(fmt = "invalid argument: {}", _0)]
+    InvalidArgument(String),
+    /// The backend failed to read, e.g. an I/O or network error.
+    #[display(fmt = "storage error: {}", _0)]
+    Storage(String),
+    /// A value that must exist in the store is missing.
+    #[display(fmt = "not found: {}", _0)]
+    NotFound(String),
+    /// The store data contradicts itself, e.g. a listed key has no value.
+    #[display(fmt = "inconsistent store: {}", _0)]
+    Inconsistent(String),
+    /// A stored value could not be decoded.
+    #[display(fmt = "decode error: {}", _0)]
+    Decode(String),
+    /// The requested height is older than the earliest retained height.
+    #[display(fmt = "height {} has been pruned", _0)]
+    HeightPruned(u64),
+    /// The requested height is newer than the latest committed height.
+    #[display(fmt = "height {} is not available yet", _0)]
+    HeightNotAvailable(u64),
+    #[display(fmt = "unimplemented: {}", _0)]
+    Unimplemented(String),
+    /// The client used up its rate limit and may retry after the duration.
+    #[display(fmt = "rate limited, retry after {:?}", _0)]
+    RateLimited(Duration),
+    /// The server is misconfigured, e.g. with an invalid listen address.
+    #[display(fmt = "invalid config: {}", _0)]
+    Config(String),
+    /// The server failed to bind, set up TLS or serve connections.
+    #[display(fmt = "transport error: {}", _0)]
+    Transport(tonic::transport::Error),
+}
+
+impl ServerError {
+    /// The gRPC code a failed request reports for this error, so that clients
+    /// can tell retryable failures apart from permanent ones.
+    pub fn code(&self) -> Code {
+        match self {
+            ServerError::ValidateIdentifier(_)
+            | ServerError::FromUtf8(_)
+            | ServerError::InvalidArgument(_) => Code::InvalidArgument,
+            ServerError::Storage(_) => Code::Unavailable,
+            ServerError::NotFound(_) => Code::NotFound,
+            ServerError::Inconsistent(_) | ServerError::Decode(_) => Code::DataLoss,
+            ServerError::HeightPruned(_) => Code::FailedPrecondition,
+            ServerError::HeightNotAvailable(_) => Code::OutOfRange,
+            ServerError::Unimplemented(_) => Code::Unimplemented,
+            ServerError::RateLimited(_) => Code::ResourceExhausted,
+            ServerError::Config(_) | ServerError::Transport(_) => Code::Internal,
+        }
+    }
+
+    /// A stable, machine-readable identifier of the error kind, reported as
+    /// the `reason` of the `google.rpc.ErrorInfo` details.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ServerError::ValidateIdentifier(_) => "INVALID_IDENTIFIER",
+            ServerError::FromUtf8(_) => "INVALID_UTF8",
+            ServerError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            ServerError::Storage(_) => "STORAGE_FAILURE",
+            ServerError::NotFound(_) => "NOT_FOUND",
+            ServerError::Inconsistent(_) => "INCONSISTENT_STORE",
+            ServerError::Decode(_) => "DECODE_FAILURE",
+            ServerError::HeightPruned(_) => "HEIGHT_PRUNED",
+            ServerError::HeightNotAvailable(_) => "HEIGHT_NOT_AVAILABLE",
+            ServerError::Unimplemented(_) => "UNIMPLEMENTED",
+            ServerError::RateLimited(_) => "RATE_LIMITED",
+            ServerError::Config(_) => "INVALID_CONFIG",
+            ServerError::Transport(_) => "TRANSPORT_FAILURE",
+        }
+    }
+
+    /// Converts the error into a `Status` carrying a `google.rpc.ErrorInfo`
+    /// detail, with `metadata` (e.g. the offending `field` or `path`) added to
+    /// the error info.
+    pub fn into_status_with<K, I>(self, metadata: I) -> Status
+    where
+        K: Into<String>,
+        I: IntoIterator<Item = (K, String)>,
+    {
+        let mut metadata: HashMap<String, String> = metadata
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect();
+        if let ServerError::HeightPruned(height) | ServerError::HeightNotAvailable(height) = &self {
+            metadata.insert("height".to_owned(), height.to_string());
+        }
+
+        if let ServerError::RateLimited(retry_after) = &self {
+            metadata.insert(
+                "retry_after_ms".to_owned(),
+                retry_after.as_millis().to_string(),
+            );
+        }
+
+        let code = self.code();
+        let message = self.to_string();
+        let error_info = ErrorInfo {
+            reason: self.reason().to_owned(),
+            domain: ERROR_DOMAIN.to_owned(),
+            metadata,
+        };
+        let mut details = vec![Any {
+            type_url: ERROR_INFO_TYPE_URL.to_owned(),
+            value: error_info.encode_to_vec(),
+        }];
+        if let ServerError::RateLimited(retry_after) = &self {
+            let retry_info = RetryInfo {
+                retry_delay: Some(ProtoDuration {
+                    seconds: retry_after.as_secs() as i64,
+                    nanos: retry_after.subsec_nanos() as i32,
+                }),
+            };
+            details.push(Any {
+                type_url: RETRY_INFO_TYPE_URL.to_owned(),
+                value: retry_info.encode_to_vec(),
+            });
+        }
+        let details = RpcStatus {
+            code: code as i32,
+            message: message.clone(),
+            details,
+        };
+
+        Status::with_details(code, message, details.encode_to_vec().into())
+    }
+}
+
+/// `google.rpc.Status`, the envelope of the `grpc-status-details-bin` trailer.
+#[derive(Clone, PartialEq, Message)]
+struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<Any>,
+}
+
+/// `google.rpc.ErrorInfo`.
+#[derive(Clone, PartialEq, Message)]
+struct ErrorInfo {
+    #[prost(string, tag = "1")]
+    reason: String,
+    #[prost(string, tag = "2")]
+    domain: String,
+    #[prost(map = "string, string", tag = "3")]
+    metadata: HashMap<String, String>,
+}
+
+/// `google.rpc.RetryInfo`.
+#[derive(Clone, PartialEq, Message)]
+struct RetryInfo {
+    #[prost(message, optional, tag = "1")]
+    retry_delay: Option<ProtoDuration>,
+}
+
+/// `google.protobuf.Duration`.
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDuration {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+impl From<ValidationError> for ServerError {
+    fn from(err: ValidationError) -> Self {
+        ServerError::ValidateIdentifier(err)
+    }
 }
 
 impl From<ServerError> for String {
@@ -14,3 +196,64 @@ impl From<ServerError> for String {
         err.to_string()
     }
 }
+
+impl From<ServerError> for Status {
+    fn from(err: ServerError) -> Self {
+        err.into_status_with(Vec::<(String, String)>::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_from_server_error() {
+        let status = Status::from(ServerError::HeightPruned(10));
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "height 10 has been pruned");
+
+        let status = Status::from(ServerError::Storage("disk failure".to_owned()));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "storage error: disk failure");
+    }
+
+    #[test]
+    fn test_status_error_info_details() {
+        let status = ServerError::HeightNotAvailable(42)
+            .into_status_with([("path", "clients/07-tendermint-0/clientState".to_owned())]);
+
+        let details = RpcStatus::decode(status.details()).unwrap();
+        assert_eq!(details.code, Code::OutOfRange as i32);
+        assert_eq!(details.details.len(), 1);
+        assert_eq!(details.details[0].type_url, ERROR_INFO_TYPE_URL);
+
+        let error_info = ErrorInfo::decode(details.details[0].value.as_slice()).unwrap();
+        assert_eq!(error_info.reason, "HEIGHT_NOT_AVAILABLE");
+        assert_eq!(error_info.domain, ERROR_DOMAIN);
+        assert_eq!(error_info.metadata["height"], "42");
+        assert_eq!(
+            error_info.metadata["path"],
+            "clients/07-tendermint-0/clientState"
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_retry_info() {
+        let status = Status::from(ServerError::RateLimited(Duration::from_millis(1500)));
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let details = RpcStatus::decode(status.details()).unwrap();
+        assert_eq!(details.details.len(), 2);
+        assert_eq!(details.details[1].type_url, RETRY_INFO_TYPE_URL);
+
+        let retry_info = RetryInfo::decode(details.details[1].value.as_slice()).unwrap();
+        assert_eq!(
+            retry_info.retry_delay,
+            Some(ProtoDuration {
+                seconds: 1,
+                nanos: 500_000_000
+            })
+        );
+    }
+}
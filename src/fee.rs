@@ -0,0 +1,550 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use ibc::applications::transfer::{Amount, RawCoin};
+use ibc::core::ics04_channel::packet::Sequence;
+use ibc::core::ics24_host::identifier::{ChannelId, PortId};
+use ibc_proto::cosmos::base::v1beta1::Coin;
+use ibc_proto::ibc::applications::fee::v1::{
+    query_server::Query as FeeQuery, Fee as RawFee, FeeEnabledChannel,
+    IdentifiedPacketFees as RawIdentifiedPacketFees, PacketFee as RawPacketFee,
+    QueryCounterpartyPayeeRequest, QueryCounterpartyPayeeResponse, QueryFeeEnabledChannelRequest,
+    QueryFeeEnabledChannelResponse, QueryFeeEnabledChannelsRequest,
+    QueryFeeEnabledChannelsResponse, QueryIncentivizedPacketRequest,
+    QueryIncentivizedPacketResponse, QueryIncentivizedPacketsForChannelRequest,
+    QueryIncentivizedPacketsForChannelResponse, QueryIncentivizedPacketsRequest,
+    QueryIncentivizedPacketsResponse, QueryPayeeRequest, QueryPayeeResponse,
+    QueryTotalAckFeesRequest, QueryTotalAckFeesResponse, QueryTotalRecvFeesRequest,
+    QueryTotalRecvFeesResponse, QueryTotalTimeoutFeesRequest, QueryTotalTimeoutFeesResponse,
+};
+use ibc_proto::ibc::core::channel::v1::PacketId;
+use tonic::{Request, Response, Status};
+
+use crate::instrument::timed_blocking;
+use crate::pagination::paginate;
+use crate::service::{invalid_field, parse_channel, parse_sequence};
+use crate::telemetry::record_span_field;
+use crate::types::{Fee, IdentifiedPacketFees, PacketFee};
+use crate::{Result, ServerError, StoreHeight};
+
+/// The ICS-29 fee middleware state of the chain, queried by the fee query
+/// service. It is an extension of [`IbcStore`](crate::IbcStore), given to
+/// [`IbcGrpcServiceBuilder::fee_store`](crate::IbcGrpcServiceBuilder::fee_store)
+/// by the chains running the fee middleware. Calls run on the blocking pool.
+pub trait IbcFeeStore: Sync + Send {
+    /// Returns the fees escrowed for every packet not yet relayed.
+    fn get_incentivized_packets(&self, height: StoreHeight) -> Result<Vec<IdentifiedPacketFees>>;
+
+    /// Returns the fees escrowed for the packets sent over `channel_id`. The
+    /// default filters [`get_incentivized_packets`](Self::get_incentivized_packets).
+    fn get_incentivized_packets_for_channel(
+        &self,
+        height: StoreHeight,
+        port_id: &PortId,
+        channel_id: &ChannelId,
+    ) -> Result<Vec<IdentifiedPacketFees>> {
+        let mut packets = self.get_incentivized_packets(height)?;
+        packets.retain(|packet| packet.port_id == *port_id && packet.channel_id == *channel_id);
+        Ok(packets)
+    }
+
+    /// Returns the fees escrowed for a packet, or `None` if it is not
+    /// incentivized.
+    fn get_packet_fees(
+        &self,
+        height: StoreHeight,
+        port_id: &PortId,
+        channel_id: &ChannelId,
+        sequence: Sequence,
+    ) -> Result<Option<Vec<PacketFee>>>;
+
+    /// Returns the address receiving the fees of `relayer` on `channel_id`.
+    fn get_payee(
+        &self,
+        height: StoreHeight,
+        channel_id: &ChannelId,
+        relayer: &str,
+    ) -> Result<Option<String>>;
+
+    /// Returns the counterparty address `relayer` registered on `channel_id`
+    /// to receive the fees of the packets it relays.
+    fn get_counterparty_payee(
+        &self,
+        height: StoreHeight,
+        channel_id: &ChannelId,
+        relayer: &str,
+    ) -> Result<Option<String>>;
+
+    fn get_fee_enabled_channels(&self, height: StoreHeight) -> Result<Vec<(PortId, ChannelId)>>;
+
+    /// The default looks the channel up in
+    /// [`get_fee_enabled_channels`](Self::get_fee_enabled_channels).
+    fn is_fee_enabled(
+        &self,
+        height: StoreHeight,
+        port_id: &PortId,
+        channel_id: &ChannelId,
+    ) -> Result<bool> {
+        Ok(self
+            .get_fee_enabled_channels(height)?
+            .iter()
+            .any(|(port, channel)| port == port_id && channel == channel_id))
+    }
+}
+
+/// A fee store shared by the fee query services.
+#[derive(Clone)]
+pub(crate) struct SharedFeeStore(pub(crate) Arc<dyn IbcFeeStore>);
+
+impl std::fmt::Debug for SharedFeeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedFeeStore")
+    }
+}
+
+/// The height of a query, where 0 stands for the latest height.
+fn query_height(height: u64) -> StoreHeight {
+    match height {
+        0 => StoreHeight::Latest,
+        height => StoreHeight::Stable(height),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_packet_id(
+    packet_id: Option<&PacketId>,
+) -> std::result::Result<(PortId, ChannelId, Sequence), Status> {
+    let packet_id = packet_id.ok_or_else(|| {
+        ServerError::InvalidArgument("missing packet id".to_owned())
+            .into_status_with([("field", "packet_id".to_owned())])
+    })?;
+    let (port_id, channel_id) = parse_channel(&packet_id.port_id, &packet_id.channel_id)?;
+    let sequence = parse_sequence(packet_id.sequence)?;
+    record_span_field("sequences", &1);
+    Ok((port_id, channel_id, sequence))
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_relayer(relayer: &str) -> std::result::Result<&str, Status> {
+    let relayer = relayer.trim();
+    if relayer.is_empty() {
+        return Err(
+            ServerError::InvalidArgument("empty relayer address".to_owned())
+                .into_status_with([("field", "relayer".to_owned())]),
+        );
+    }
+    Ok(relayer)
+}
+
+fn raw_fee(fee: Fee) -> RawFee {
+    let raw_coins = |coins: Vec<RawCoin>| coins.into_iter().map(Coin::from).collect();
+    RawFee {
+        recv_fee: raw_coins(fee.recv_fee),
+        ack_fee: raw_coins(fee.ack_fee),
+        timeout_fee: raw_coins(fee.timeout_fee),
+    }
+}
+
+fn raw_packet_fee(fee: PacketFee) -> RawPacketFee {
+    RawPacketFee {
+        fee: Some(raw_fee(fee.fee)),
+        refund_address: fee.refund_address,
+        relayers: fee.relayers,
+    }
+}
+
+fn raw_identified_packet_fees(packet: IdentifiedPacketFees) -> RawIdentifiedPacketFees {
+    RawIdentifiedPacketFees {
+        packet_id: Some(PacketId {
+            port_id: packet.port_id.to_string(),
+            channel_id: packet.channel_id.to_string(),
+            sequence: packet.sequence.into(),
+        }),
+        packet_fees: packet.packet_fees.into_iter().map(raw_packet_fee).collect(),
+    }
+}
+
+/// Sums the coins of `fees` per denom, e.g. the receive fees escrowed by
+/// every payer of a packet.
+fn total_fees<'a>(fees: impl IntoIterator<Item = &'a Vec<RawCoin>>) -> Result<Vec<Coin>> {
+    let mut totals: BTreeMap<&str, Amount> = BTreeMap::new();
+    for coin in fees.into_iter().flatten() {
+        let total = totals.entry(&coin.denom).or_insert_with(|| Amount::from(0));
+        *total = total
+            .checked_add(coin.amount)
+            .ok_or_else(|| ServerError::Decode(format!("total fee of {} overflows", coin.denom)))?;
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(denom, amount)| Coin {
+            denom: denom.to_owned(),
+            amount: amount.to_string(),
+        })
+        .collect())
+}
+
+/// Serves `ibc.applications.fee.v1.Query` from an [`IbcFeeStore`].
+pub struct IbcFeeService {
+    store: Arc<dyn IbcFeeStore>,
+}
+
+impl IbcFeeService {
+    pub(crate) fn new(store: SharedFeeStore) -> Self {
+        Self { store: store.0 }
+    }
+
+    /// Returns the fees of a packet, failing with `NOT_FOUND` if it is not
+    /// incentivized.
+    async fn packet_fees(
+        &self,
+        packet_id: Option<&PacketId>,
+        height: StoreHeight,
+    ) -> std::result::Result<Vec<PacketFee>, Status> {
+        let (port_id, channel_id, sequence) = parse_packet_id(packet_id)?;
+        let not_found = format!("fees of packet {}/{}/{}", port_id, channel_id, sequence);
+        let fees = timed_blocking(&self.store, "get_packet_fees", move |store| {
+            store.get_packet_fees(height, &port_id, &channel_id, sequence)
+        })
+        .await?
+        .ok_or(ServerError::NotFound(not_found))?;
+        Ok(fees)
+    }
+}
+
+#[tonic::async_trait]
+impl FeeQuery for IbcFeeService {
+    async fn incentivized_packets(
+        &self,
+        request: Request<QueryIncentivizedPacketsRequest>,
+    ) -> std::result::Result<Response<QueryIncentivizedPacketsResponse>, Status> {
+        let request = request.into_inner();
+        let height = query_height(request.query_height);
+        let packets = timed_blocking(&self.store, "get_incentivized_packets", move |store| {
+            store.get_incentivized_packets(height)
+        })
+        .await?;
+        // The response has no page of its own to return the next key in.
+        let (packets, _) =
+            paginate(packets, request.pagination).map_err(invalid_field("pagination"))?;
+        Ok(Response::new(QueryIncentivizedPacketsResponse {
+            incentivized_packets: packets
+                .into_iter()
+                .map(raw_identified_packet_fees)
+                .collect(),
+        }))
+    }
+
+    async fn incentivized_packet(
+        &self,
+        request: Request<QueryIncentivizedPacketRequest>,
+    ) -> std::result::Result<Response<QueryIncentivizedPacketResponse>, Status> {
+        let request = request.into_inner();
+        let packet_fees = self
+            .packet_fees(
+                request.packet_id.as_ref(),
+                query_height(request.query_height),
+            )
+            .await?;
+        Ok(Response::new(QueryIncentivizedPacketResponse {
+            incentivized_packet: Some(RawIdentifiedPacketFees {
+                packet_id: request.packet_id,
+                packet_fees: packet_fees.into_iter().map(raw_packet_fee).collect(),
+            }),
+        }))
+    }
+
+    async fn incentivized_packets_for_channel(
+        &self,
+        request: Request<QueryIncentivizedPacketsForChannelRequest>,
+    ) -> std::result::Result<Response<QueryIncentivizedPacketsForChannelResponse>, Status> {
+        let request = request.into_inner();
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
+        let height = query_height(request.query_height);
+        let packets = timed_blocking(
+            &self.store,
+            "get_incentivized_packets_for_channel",
+            move |store| store.get_incentivized_packets_for_channel(height, &port_id, &channel_id),
+        )
+        .await?;
+        let (packets, _) =
+            paginate(packets, request.pagination).map_err(invalid_field("pagination"))?;
+        Ok(Response::new(QueryIncentivizedPacketsForChannelResponse {
+            incentivized_packets: packets
+                .into_iter()
+                .map(raw_identified_packet_fees)
+                .collect(),
+        }))
+    }
+
+    async fn total_recv_fees(
+        &self,
+        request: Request<QueryTotalRecvFeesRequest>,
+    ) -> std::result::Result<Response<QueryTotalRecvFeesResponse>, Status> {
+        let packet_fees = self
+            .packet_fees(request.get_ref().packet_id.as_ref(), StoreHeight::Latest)
+            .await?;
+        let recv_fees = total_fees(packet_fees.iter().map(|fee| &fee.fee.recv_fee))?;
+        Ok(Response::new(QueryTotalRecvFeesResponse { recv_fees }))
+    }
+
+    async fn total_ack_fees(
+        &self,
+        request: Request<QueryTotalAckFeesRequest>,
+    ) -> std::result::Result<Response<QueryTotalAckFeesResponse>, Status> {
+        let packet_fees = self
+            .packet_fees(request.get_ref().packet_id.as_ref(), StoreHeight::Latest)
+            .await?;
+        let ack_fees = total_fees(packet_fees.iter().map(|fee| &fee.fee.ack_fee))?;
+        Ok(Response::new(QueryTotalAckFeesResponse { ack_fees }))
+    }
+
+    async fn total_timeout_fees(
+        &self,
+        request: Request<QueryTotalTimeoutFeesRequest>,
+    ) -> std::result::Result<Response<QueryTotalTimeoutFeesResponse>, Status> {
+        let packet_fees = self
+            .packet_fees(request.get_ref().packet_id.as_ref(), StoreHeight::Latest)
+            .await?;
+        let timeout_fees = total_fees(packet_fees.iter().map(|fee| &fee.fee.timeout_fee))?;
+        Ok(Response::new(QueryTotalTimeoutFeesResponse {
+            timeout_fees,
+        }))
+    }
+
+    async fn payee(
+        &self,
+        request: Request<QueryPayeeRequest>,
+    ) -> std::result::Result<Response<QueryPayeeResponse>, Status> {
+        let request = request.into_inner();
+        let channel_id = request
+            .channel_id
+            .parse::<ChannelId>()
+            .map_err(invalid_field("channel_id"))?;
+        record_span_field("channel_id", &channel_id);
+        let relayer = parse_relayer(&request.relayer)?.to_owned();
+
+        let not_found = format!("payee of {} on {}", relayer, channel_id);
+        let payee_address = timed_blocking(&self.store, "get_payee", move |store| {
+            store.get_payee(StoreHeight::Latest, &channel_id, &relayer)
+        })
+        .await?
+        .ok_or(ServerError::NotFound(not_found))?;
+        Ok(Response::new(QueryPayeeResponse { payee_address }))
+    }
+
+    async fn counterparty_payee(
+        &self,
+        request: Request<QueryCounterpartyPayeeRequest>,
+    ) -> std::result::Result<Response<QueryCounterpartyPayeeResponse>, Status> {
+        let request = request.into_inner();
+        let channel_id = request
+            .channel_id
+            .parse::<ChannelId>()
+            .map_err(invalid_field("channel_id"))?;
+        record_span_field("channel_id", &channel_id);
+        let relayer = parse_relayer(&request.relayer)?.to_owned();
+
+        let not_found = format!("counterparty payee of {} on {}", relayer, channel_id);
+        let counterparty_payee =
+            timed_blocking(&self.store, "get_counterparty_payee", move |store| {
+                store.get_counterparty_payee(StoreHeight::Latest, &channel_id, &relayer)
+            })
+            .await?
+            .ok_or(ServerError::NotFound(not_found))?;
+        Ok(Response::new(QueryCounterpartyPayeeResponse {
+            counterparty_payee,
+        }))
+    }
+
+    async fn fee_enabled_channels(
+        &self,
+        request: Request<QueryFeeEnabledChannelsRequest>,
+    ) -> std::result::Result<Response<QueryFeeEnabledChannelsResponse>, Status> {
+        let request = request.into_inner();
+        let height = query_height(request.query_height);
+        let channels = timed_blocking(&self.store, "get_fee_enabled_channels", move |store| {
+            store.get_fee_enabled_channels(height)
+        })
+        .await?;
+        let (channels, _) =
+            paginate(channels, request.pagination).map_err(invalid_field("pagination"))?;
+        Ok(Response::new(QueryFeeEnabledChannelsResponse {
+            fee_enabled_channels: channels
+                .into_iter()
+                .map(|(port_id, channel_id)| FeeEnabledChannel {
+                    port_id: port_id.to_string(),
+                    channel_id: channel_id.to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn fee_enabled_channel(
+        &self,
+        request: Request<QueryFeeEnabledChannelRequest>,
+    ) -> std::result::Result<Response<QueryFeeEnabledChannelResponse>, Status> {
+        let request = request.into_inner();
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
+        let fee_enabled = timed_blocking(&self.store, "is_fee_enabled", move |store| {
+            store.is_fee_enabled(StoreHeight::Latest, &port_id, &channel_id)
+        })
+        .await?;
+        Ok(Response::new(QueryFeeEnabledChannelResponse {
+            fee_enabled,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc_proto::cosmos::base::query::v1beta1::PageRequest;
+
+    use super::*;
+
+    fn coin(denom: &str, amount: &str) -> RawCoin {
+        RawCoin {
+            denom: denom.to_owned(),
+            amount: amount.parse().unwrap(),
+        }
+    }
+
+    fn raw_coin(denom: &str, amount: &str) -> Coin {
+        Coin {
+            denom: denom.to_owned(),
+            amount: amount.to_owned(),
+        }
+    }
+
+    /// A store with three packets incentivized on `transfer/channel-0`.
+    struct PacketsStore;
+
+    impl IbcFeeStore for PacketsStore {
+        fn get_incentivized_packets(
+            &self,
+            _height: StoreHeight,
+        ) -> Result<Vec<IdentifiedPacketFees>> {
+            Ok((1..=3)
+                .map(|sequence| IdentifiedPacketFees {
+                    port_id: PortId::transfer(),
+                    channel_id: ChannelId::new(0),
+                    sequence: Sequence::from(sequence),
+                    packet_fees: vec![PacketFee {
+                        fee: Fee {
+                            recv_fee: vec![coin("uatom", "10")],
+                            ..Default::default()
+                        },
+                        refund_address: "cosmos1refund".to_owned(),
+                        relayers: vec![],
+                    }],
+                })
+                .collect())
+        }
+
+        fn get_packet_fees(
+            &self,
+            _height: StoreHeight,
+            _port_id: &PortId,
+            _channel_id: &ChannelId,
+            _sequence: Sequence,
+        ) -> Result<Option<Vec<PacketFee>>> {
+            Ok(None)
+        }
+
+        fn get_payee(
+            &self,
+            _height: StoreHeight,
+            _channel_id: &ChannelId,
+            _relayer: &str,
+        ) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn get_counterparty_payee(
+            &self,
+            _height: StoreHeight,
+            _channel_id: &ChannelId,
+            _relayer: &str,
+        ) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn get_fee_enabled_channels(
+            &self,
+            _height: StoreHeight,
+        ) -> Result<Vec<(PortId, ChannelId)>> {
+            Ok(vec![(PortId::transfer(), ChannelId::new(0))])
+        }
+    }
+
+    #[test]
+    fn test_total_fees() {
+        let fees = vec![
+            vec![coin("uatom", "10"), coin("stake", "1")],
+            vec![coin("uatom", "5")],
+        ];
+        assert_eq!(
+            total_fees(&fees).unwrap(),
+            vec![raw_coin("stake", "1"), raw_coin("uatom", "15")]
+        );
+
+        let max = "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+        assert!(total_fees(&vec![vec![coin("uatom", max), coin("uatom", "1")]]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_incentivized_packets_page() {
+        let service = IbcFeeService::new(SharedFeeStore(Arc::new(PacketsStore)));
+        let packets = service
+            .incentivized_packets_for_channel(Request::new(
+                QueryIncentivizedPacketsForChannelRequest {
+                    pagination: Some(PageRequest {
+                        offset: 1,
+                        limit: 1,
+                        ..Default::default()
+                    }),
+                    port_id: "transfer".to_owned(),
+                    channel_id: "channel-0".to_owned(),
+                    query_height: 0,
+                },
+            ))
+            .await
+            .unwrap()
+            .into_inner()
+            .incentivized_packets;
+        assert_eq!(packets.len(), 1);
+        assert_eq!(
+            packets[0].packet_id,
+            Some(PacketId {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-0".to_owned(),
+                sequence: 2,
+            })
+        );
+        let fee = packets[0].packet_fees[0].fee.as_ref().unwrap();
+        assert_eq!(fee.recv_fee, vec![raw_coin("uatom", "10")]);
+
+        let status = service
+            .incentivized_packets(Request::new(QueryIncentivizedPacketsRequest {
+                pagination: Some(PageRequest {
+                    key: vec![1],
+                    ..Default::default()
+                }),
+                query_height: 0,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_parse_packet_id() {
+        let packet_id = PacketId {
+            port_id: "transfer".to_owned(),
+            channel_id: "channel-0".to_owned(),
+            sequence: 0,
+        };
+        let status = parse_packet_id(Some(&packet_id)).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(parse_packet_id(None).is_err());
+    }
+}
@@ -0,0 +1,826 @@
+// Errors are `Status`es, as returned by the gRPC handlers the gateway calls.
+#![allow(clippy::result_large_err)]
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use http::{Method, Request, Response, StatusCode};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Body;
+use ibc_proto::cosmos::base::query::v1beta1::PageRequest;
+use ibc_proto::ibc::core::{
+    channel::v1::{
+        query_server::{Query as ChannelQuery, QueryServer as ChannelQueryServer},
+        QueryChannelClientStateRequest, QueryChannelConsensusStateRequest, QueryChannelRequest,
+        QueryChannelsRequest, QueryConnectionChannelsRequest, QueryNextSequenceReceiveRequest,
+        QueryPacketAcknowledgementRequest, QueryPacketAcknowledgementsRequest,
+        QueryPacketCommitmentRequest, QueryPacketCommitmentsRequest, QueryPacketReceiptRequest,
+        QueryUnreceivedAcksRequest, QueryUnreceivedPacketsRequest,
+    },
+    client::v1::{
+        query_server::{Query as ClientQuery, QueryServer as ClientQueryServer},
+        QueryClientParamsRequest, QueryClientStateRequest, QueryClientStatesRequest,
+        QueryClientStatusRequest, QueryConsensusStateHeightsRequest, QueryConsensusStateRequest,
+        QueryConsensusStatesRequest, QueryUpgradedClientStateRequest,
+        QueryUpgradedConsensusStateRequest,
+    },
+    connection::v1::{
+        query_server::{Query as ConnectionQuery, QueryServer as ConnectionQueryServer},
+        QueryClientConnectionsRequest, QueryConnectionClientStateRequest,
+        QueryConnectionConsensusStateRequest, QueryConnectionRequest, QueryConnectionsRequest,
+    },
+};
+use serde_json::{json, Value};
+use tonic::transport::NamedService;
+use tonic::{Code, Status};
+use tracing::Instrument;
+
+use crate::auth::{ApiKeyLabel, ApiKeys};
+use crate::catch_panic::{catch_unwind, install_location_hook, panic_status};
+use crate::error::ServerError;
+use crate::json::ToJson;
+use crate::metrics::{METRICS, UNKNOWN_METHOD};
+use crate::rate_limit::{rate_limit_key, RateLimiter};
+use crate::service::{IbcChannelService, IbcClientService, IbcConnectionService};
+use crate::telemetry::request_span;
+use crate::AsyncIbcStore;
+
+type Call<'a> = BoxFuture<'a, Result<Value, Status>>;
+
+/// Serves the Cosmos SDK REST routes of the IBC queries, e.g.
+/// `GET /ibc/core/channel/v1/channels`, by calling the gRPC handlers and
+/// rendering their responses as proto3 JSON.
+///
+/// The list queries return every entry, as the gRPC handlers do: the
+/// `pagination.*` parameters are validated and passed on, but no page is
+/// cut and the responses carry no `pagination`, so clients must not expect a
+/// `next_key` or `total`.
+pub(crate) struct RestGateway<Store: AsyncIbcStore> {
+    pub(crate) client: Option<IbcClientService<Store>>,
+    pub(crate) connection: Option<IbcConnectionService<Store>>,
+    pub(crate) channel: Option<IbcChannelService<Store>>,
+    pub(crate) api_keys: Option<ApiKeys>,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// Serves `gateway` on `addr` until the returned task is aborted.
+pub(crate) fn spawn_rest_gateway<Store>(
+    addr: SocketAddr,
+    gateway: RestGateway<Store>,
+) -> crate::Result<tokio::task::JoinHandle<()>>
+where
+    Store: AsyncIbcStore + 'static,
+{
+    let builder = hyper::Server::try_bind(&addr)
+        .map_err(|e| ServerError::Config(format!("failed to bind REST gateway {}: {}", addr, e)))?;
+    install_location_hook();
+
+    let gateway = Arc::new(gateway);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let gateway = Arc::clone(&gateway);
+        let remote_addr = conn.remote_addr();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let gateway = Arc::clone(&gateway);
+                async move { Ok::<_, Infallible>(gateway.handle(req, remote_addr).await) }
+            }))
+        }
+    });
+
+    log::info!("REST gateway listening on {}", addr);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = builder.serve(make_service).await {
+            log::error!("REST gateway failed: {}", e);
+        }
+    }))
+}
+
+impl<Store: AsyncIbcStore + 'static> RestGateway<Store> {
+    /// Answers `req` the way the gRPC server would answer the method it maps
+    /// to: in a request span, recorded in the request metrics.
+    async fn handle(&self, req: Request<Body>, remote_addr: SocketAddr) -> Response<Body> {
+        let start = Instant::now();
+        let authenticated = self.authenticate(&req);
+        let label = authenticated.as_ref().ok().cloned().flatten();
+        let params = QueryParams::parse(req.uri().query());
+        let segments: Vec<&str> = req.uri().path().trim_matches('/').split('/').collect();
+        let route = self.route(&segments, &params, &label);
+        let grpc_method = route
+            .as_ref()
+            .map(|(service, method, _)| format!("/{}/{}", service, method));
+        let span = request_span(
+            req.headers(),
+            grpc_method.as_deref().unwrap_or(req.uri().path()),
+        );
+
+        let result = async {
+            if req.method() != Method::GET {
+                return Err(Status::unimplemented(format!(
+                    "method {} is not supported",
+                    req.method()
+                )));
+            }
+            authenticated?;
+            let (_, method, call) = route
+                .ok_or_else(|| Status::not_found(format!("no route for {}", req.uri().path())))?;
+            self.dispatch(method, call, label.as_ref(), remote_addr)
+                .await
+        }
+        .instrument(span)
+        .await;
+
+        let code = result.as_ref().err().map_or(Code::Ok, Status::code);
+        METRICS.observe_request(
+            grpc_method.as_deref().unwrap_or(UNKNOWN_METHOD),
+            start.elapsed(),
+            code,
+        );
+
+        let (status_code, body) = match result {
+            Ok(body) => (StatusCode::OK, body),
+            Err(status) => (
+                http_status(status.code()),
+                json!({
+                    "code": status.code() as i32,
+                    "message": status.message(),
+                    "details": [],
+                }),
+            ),
+        };
+        Response::builder()
+            .status(status_code)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("valid response")
+    }
+
+    fn authenticate(&self, req: &Request<Body>) -> Result<Option<ApiKeyLabel>, Status> {
+        let keys = match &self.api_keys {
+            Some(keys) => keys,
+            None => return Ok(None),
+        };
+        let authorization = req
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        keys.authenticate(authorization).map(Some)
+    }
+
+    async fn dispatch(
+        &self,
+        method: &'static str,
+        call: Call<'_>,
+        label: Option<&ApiKeyLabel>,
+        remote_addr: SocketAddr,
+    ) -> Result<Value, Status> {
+        if let Some(limiter) = &self.rate_limiter {
+            let client = rate_limit_key(label, Some(remote_addr));
+            if let Err(retry_after) = limiter.try_acquire(&client, method) {
+                return Err(ServerError::RateLimited(retry_after)
+                    .into_status_with([("method", method.to_owned())]));
+            }
+        }
+
+        match catch_unwind(call).await {
+            Ok(result) => result,
+            Err(panic) => Err(panic_status(method, panic)),
+        }
+    }
+
+    /// Maps a REST path to the names of the gRPC service and method serving
+    /// it and the call of its handler.
+    fn route<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &QueryParams,
+        label: &Option<ApiKeyLabel>,
+    ) -> Option<(&'static str, &'static str, Call<'a>)> {
+        let (service, route) = match segments {
+            ["ibc", "core", "client", "v1", rest @ ..] => (
+                ClientQueryServer::<IbcClientService<Store>>::NAME,
+                self.route_client(rest, params, label),
+            ),
+            ["ibc", "client", "v1", "params"] => (
+                ClientQueryServer::<IbcClientService<Store>>::NAME,
+                self.route_client(&["params"], params, label),
+            ),
+            ["ibc", "core", "connection", "v1", rest @ ..] => (
+                ConnectionQueryServer::<IbcConnectionService<Store>>::NAME,
+                self.route_connection(rest, params, label),
+            ),
+            ["ibc", "core", "channel", "v1", rest @ ..] => (
+                ChannelQueryServer::<IbcChannelService<Store>>::NAME,
+                self.route_channel(rest, params, label),
+            ),
+            _ => return None,
+        };
+        route.map(|(method, call)| (service, method, call))
+    }
+
+    fn route_client<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &QueryParams,
+        label: &Option<ApiKeyLabel>,
+    ) -> Option<(&'static str, Call<'a>)> {
+        let service = self.client.as_ref();
+        let label = label.clone();
+        let route: (&'static str, Call<'a>) = match segments {
+            ["client_states"] => {
+                let request = params
+                    .pagination()
+                    .map(|pagination| grpc_request(QueryClientStatesRequest { pagination }, label));
+                (
+                    "ClientStates",
+                    call(service, request, |s, r| s.client_states(r)),
+                )
+            }
+            ["client_states", client_id] => {
+                let request = grpc_request(
+                    QueryClientStateRequest {
+                        client_id: client_id.to_string(),
+                    },
+                    label,
+                );
+                (
+                    "ClientState",
+                    call(service, Ok(request), |s, r| s.client_state(r)),
+                )
+            }
+            ["consensus_states", client_id] => {
+                let client_id = client_id.to_string();
+                let request = params.pagination().map(|pagination| {
+                    grpc_request(
+                        QueryConsensusStatesRequest {
+                            client_id,
+                            pagination,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "ConsensusStates",
+                    call(service, request, |s, r| s.consensus_states(r)),
+                )
+            }
+            ["consensus_states", client_id, "heights"] => {
+                let client_id = client_id.to_string();
+                let request = params.pagination().map(|pagination| {
+                    grpc_request(
+                        QueryConsensusStateHeightsRequest {
+                            client_id,
+                            pagination,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "ConsensusStateHeights",
+                    call(service, request, |s, r| s.consensus_state_heights(r)),
+                )
+            }
+            ["consensus_states", client_id, "revision", revision_number, "height", revision_height] =>
+            {
+                let client_id = client_id.to_string();
+                let request =
+                    parse_u64(revision_number, "revision_number").and_then(|revision_number| {
+                        Ok(grpc_request(
+                            QueryConsensusStateRequest {
+                                client_id,
+                                revision_number,
+                                revision_height: parse_u64(revision_height, "revision_height")?,
+                                latest_height: params.bool("latest_height")?,
+                            },
+                            label,
+                        ))
+                    });
+                (
+                    "ConsensusState",
+                    call(service, request, |s, r| s.consensus_state(r)),
+                )
+            }
+            ["client_status", client_id] => {
+                let request = grpc_request(
+                    QueryClientStatusRequest {
+                        client_id: client_id.to_string(),
+                    },
+                    label,
+                );
+                (
+                    "ClientStatus",
+                    call(service, Ok(request), |s, r| s.client_status(r)),
+                )
+            }
+            ["params"] => {
+                let request = grpc_request(QueryClientParamsRequest {}, label);
+                (
+                    "ClientParams",
+                    call(service, Ok(request), |s, r| s.client_params(r)),
+                )
+            }
+            ["upgraded_client_states"] => {
+                let request = grpc_request(QueryUpgradedClientStateRequest {}, label);
+                (
+                    "UpgradedClientState",
+                    call(service, Ok(request), |s, r| s.upgraded_client_state(r)),
+                )
+            }
+            ["upgraded_consensus_states"] => {
+                let request = grpc_request(QueryUpgradedConsensusStateRequest {}, label);
+                (
+                    "UpgradedConsensusState",
+                    call(service, Ok(request), |s, r| s.upgraded_consensus_state(r)),
+                )
+            }
+            _ => return None,
+        };
+        Some(route)
+    }
+
+    fn route_connection<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &QueryParams,
+        label: &Option<ApiKeyLabel>,
+    ) -> Option<(&'static str, Call<'a>)> {
+        let service = self.connection.as_ref();
+        let label = label.clone();
+        let route: (&'static str, Call<'a>) = match segments {
+            ["connections"] => {
+                let request = params
+                    .pagination()
+                    .map(|pagination| grpc_request(QueryConnectionsRequest { pagination }, label));
+                (
+                    "Connections",
+                    call(service, request, |s, r| s.connections(r)),
+                )
+            }
+            ["connections", connection_id] => {
+                let request = grpc_request(
+                    QueryConnectionRequest {
+                        connection_id: connection_id.to_string(),
+                    },
+                    label,
+                );
+                (
+                    "Connection",
+                    call(service, Ok(request), |s, r| s.connection(r)),
+                )
+            }
+            ["client_connections", client_id] => {
+                let request = grpc_request(
+                    QueryClientConnectionsRequest {
+                        client_id: client_id.to_string(),
+                    },
+                    label,
+                );
+                (
+                    "ClientConnections",
+                    call(service, Ok(request), |s, r| s.client_connections(r)),
+                )
+            }
+            ["connections", connection_id, "client_state"] => {
+                let request = grpc_request(
+                    QueryConnectionClientStateRequest {
+                        connection_id: connection_id.to_string(),
+                    },
+                    label,
+                );
+                (
+                    "ConnectionClientState",
+                    call(service, Ok(request), |s, r| s.connection_client_state(r)),
+                )
+            }
+            ["connections", connection_id, "consensus_state", "revision", revision_number, "height", revision_height] =>
+            {
+                let connection_id = connection_id.to_string();
+                let request =
+                    parse_u64(revision_number, "revision_number").and_then(|revision_number| {
+                        Ok(grpc_request(
+                            QueryConnectionConsensusStateRequest {
+                                connection_id,
+                                revision_number,
+                                revision_height: parse_u64(revision_height, "revision_height")?,
+                            },
+                            label,
+                        ))
+                    });
+                (
+                    "ConnectionConsensusState",
+                    call(service, request, |s, r| s.connection_consensus_state(r)),
+                )
+            }
+            _ => return None,
+        };
+        Some(route)
+    }
+
+    fn route_channel<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &QueryParams,
+        label: &Option<ApiKeyLabel>,
+    ) -> Option<(&'static str, Call<'a>)> {
+        let service = self.channel.as_ref();
+        let label = label.clone();
+        let route: (&'static str, Call<'a>) = match segments {
+            ["channels"] => {
+                let request = params
+                    .pagination()
+                    .map(|pagination| grpc_request(QueryChannelsRequest { pagination }, label));
+                ("Channels", call(service, request, |s, r| s.channels(r)))
+            }
+            ["connections", connection, "channels"] => {
+                let connection = connection.to_string();
+                let request = params.pagination().map(|pagination| {
+                    grpc_request(
+                        QueryConnectionChannelsRequest {
+                            connection,
+                            pagination,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "ConnectionChannels",
+                    call(service, request, |s, r| s.connection_channels(r)),
+                )
+            }
+            ["channels", channel_id, "ports", port_id, rest @ ..] => {
+                let port_id = port_id.to_string();
+                let channel_id = channel_id.to_string();
+                return self.route_channel_end(rest, params, label, port_id, channel_id);
+            }
+            _ => return None,
+        };
+        Some(route)
+    }
+
+    /// Routes the queries under `channels/{channel_id}/ports/{port_id}`.
+    fn route_channel_end<'a>(
+        &'a self,
+        segments: &[&str],
+        params: &QueryParams,
+        label: Option<ApiKeyLabel>,
+        port_id: String,
+        channel_id: String,
+    ) -> Option<(&'static str, Call<'a>)> {
+        let service = self.channel.as_ref();
+        let route: (&'static str, Call<'a>) = match segments {
+            [] => {
+                let request = grpc_request(
+                    QueryChannelRequest {
+                        port_id,
+                        channel_id,
+                    },
+                    label,
+                );
+                ("Channel", call(service, Ok(request), |s, r| s.channel(r)))
+            }
+            ["client_state"] => {
+                let request = grpc_request(
+                    QueryChannelClientStateRequest {
+                        port_id,
+                        channel_id,
+                    },
+                    label,
+                );
+                (
+                    "ChannelClientState",
+                    call(service, Ok(request), |s, r| s.channel_client_state(r)),
+                )
+            }
+            ["consensus_state", "revision", revision_number, "height", revision_height] => {
+                let request =
+                    parse_u64(revision_number, "revision_number").and_then(|revision_number| {
+                        Ok(grpc_request(
+                            QueryChannelConsensusStateRequest {
+                                port_id,
+                                channel_id,
+                                revision_number,
+                                revision_height: parse_u64(revision_height, "revision_height")?,
+                            },
+                            label,
+                        ))
+                    });
+                (
+                    "ChannelConsensusState",
+                    call(service, request, |s, r| s.channel_consensus_state(r)),
+                )
+            }
+            ["packet_commitments"] => {
+                let request = params.pagination().map(|pagination| {
+                    grpc_request(
+                        QueryPacketCommitmentsRequest {
+                            port_id,
+                            channel_id,
+                            pagination,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "PacketCommitments",
+                    call(service, request, |s, r| s.packet_commitments(r)),
+                )
+            }
+            ["packet_commitments", sequence] => {
+                let request = parse_u64(sequence, "sequence").map(|sequence| {
+                    grpc_request(
+                        QueryPacketCommitmentRequest {
+                            port_id,
+                            channel_id,
+                            sequence,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "PacketCommitment",
+                    call(service, request, |s, r| s.packet_commitment(r)),
+                )
+            }
+            ["packet_commitments", sequences, "unreceived_packets"] => {
+                let request = parse_u64_list(sequences, "packet_commitment_sequences").map(
+                    |packet_commitment_sequences| {
+                        grpc_request(
+                            QueryUnreceivedPacketsRequest {
+                                port_id,
+                                channel_id,
+                                packet_commitment_sequences,
+                            },
+                            label,
+                        )
+                    },
+                );
+                (
+                    "UnreceivedPackets",
+                    call(service, request, |s, r| s.unreceived_packets(r)),
+                )
+            }
+            ["packet_commitments", sequences, "unreceived_acks"] => {
+                let request =
+                    parse_u64_list(sequences, "packet_ack_sequences").map(|packet_ack_sequences| {
+                        grpc_request(
+                            QueryUnreceivedAcksRequest {
+                                port_id,
+                                channel_id,
+                                packet_ack_sequences,
+                            },
+                            label,
+                        )
+                    });
+                (
+                    "UnreceivedAcks",
+                    call(service, request, |s, r| s.unreceived_acks(r)),
+                )
+            }
+            ["packet_receipts", sequence] => {
+                let request = parse_u64(sequence, "sequence").map(|sequence| {
+                    grpc_request(
+                        QueryPacketReceiptRequest {
+                            port_id,
+                            channel_id,
+                            sequence,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "PacketReceipt",
+                    call(service, request, |s, r| s.packet_receipt(r)),
+                )
+            }
+            ["packet_acks", sequence] => {
+                let request = parse_u64(sequence, "sequence").map(|sequence| {
+                    grpc_request(
+                        QueryPacketAcknowledgementRequest {
+                            port_id,
+                            channel_id,
+                            sequence,
+                        },
+                        label,
+                    )
+                });
+                (
+                    "PacketAcknowledgement",
+                    call(service, request, |s, r| s.packet_acknowledgement(r)),
+                )
+            }
+            ["packet_acknowledgements"] => {
+                let request = params.pagination().and_then(|pagination| {
+                    Ok(grpc_request(
+                        QueryPacketAcknowledgementsRequest {
+                            port_id,
+                            channel_id,
+                            pagination,
+                            packet_commitment_sequences: params
+                                .u64_list("packet_commitment_sequences")?,
+                        },
+                        label,
+                    ))
+                });
+                (
+                    "PacketAcknowledgements",
+                    call(service, request, |s, r| s.packet_acknowledgements(r)),
+                )
+            }
+            ["next_sequence"] => {
+                let request = grpc_request(
+                    QueryNextSequenceReceiveRequest {
+                        port_id,
+                        channel_id,
+                    },
+                    label,
+                );
+                (
+                    "NextSequenceReceive",
+                    call(service, Ok(request), |s, r| s.next_sequence_receive(r)),
+                )
+            }
+            _ => return None,
+        };
+        Some(route)
+    }
+}
+
+/// Calls `handler` of `service` with `request`, failing with `UNIMPLEMENTED`
+/// when the service is disabled.
+fn call<'a, S, Req, Res, F>(
+    service: Option<&'a S>,
+    request: Result<tonic::Request<Req>, Status>,
+    handler: F,
+) -> Call<'a>
+where
+    S: Sync,
+    Req: Send + 'a,
+    Res: ToJson,
+    F: FnOnce(&'a S, tonic::Request<Req>) -> BoxFuture<'a, Result<tonic::Response<Res>, Status>>
+        + Send
+        + 'a,
+{
+    Box::pin(async move {
+        let service =
+            service.ok_or_else(|| Status::unimplemented("the query service is disabled"))?;
+        let response = handler(service, request?).await?;
+        Ok(response.into_inner().to_json()?)
+    })
+}
+
+fn grpc_request<T>(message: T, label: Option<ApiKeyLabel>) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    if let Some(label) = label {
+        request.extensions_mut().insert(label);
+    }
+    request
+}
+
+fn invalid_param(name: &str, value: &str) -> Status {
+    ServerError::InvalidArgument(format!("{} {:?}", name, value))
+        .into_status_with([("field", name.to_owned())])
+}
+
+fn parse_u64(value: &str, name: &str) -> Result<u64, Status> {
+    value.parse().map_err(|_| invalid_param(name, value))
+}
+
+/// Parses a comma separated list of integers, e.g. `1,2,3`.
+fn parse_u64_list(value: &str, name: &str) -> Result<Vec<u64>, Status> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| parse_u64(item, name))
+        .collect()
+}
+
+/// The decoded query string of a request.
+#[derive(Debug, Default)]
+struct QueryParams(Vec<(String, String)>);
+
+impl QueryParams {
+    fn parse(query: Option<&str>) -> Self {
+        let pairs = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+            .into_owned()
+            .collect();
+        Self(pairs)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, Status> {
+        match self.get(name) {
+            Some(value) => value.parse().map_err(|_| invalid_param(name, value)),
+            None => Ok(false),
+        }
+    }
+
+    fn u64(&self, name: &str) -> Result<u64, Status> {
+        match self.get(name) {
+            Some(value) => parse_u64(value, name),
+            None => Ok(0),
+        }
+    }
+
+    /// Collects a repeated parameter, given either several times or as a
+    /// comma separated list.
+    fn u64_list(&self, name: &str) -> Result<Vec<u64>, Status> {
+        let mut values = vec![];
+        for (_, value) in self.0.iter().filter(|(key, _)| key == name) {
+            values.extend(parse_u64_list(value, name)?);
+        }
+        Ok(values)
+    }
+
+    /// The `pagination.*` parameters of the Cosmos SDK list queries. The
+    /// handlers ignore them and return every entry.
+    fn pagination(&self) -> Result<Option<PageRequest>, Status> {
+        if !self.0.iter().any(|(key, _)| key.starts_with("pagination.")) {
+            return Ok(None);
+        }
+
+        let key = match self.get("pagination.key") {
+            Some(key) => base64::decode(key).map_err(|_| invalid_param("pagination.key", key))?,
+            None => vec![],
+        };
+        Ok(Some(PageRequest {
+            key,
+            offset: self.u64("pagination.offset")?,
+            limit: self.u64("pagination.limit")?,
+            count_total: self.bool("pagination.count_total")?,
+            reverse: self.bool("pagination.reverse")?,
+        }))
+    }
+}
+
+/// The HTTP status of a failed query, as mapped by grpc-gateway.
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::REQUEST_TIMEOUT,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+            StatusCode::BAD_REQUEST
+        }
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pagination_params() {
+        let params = QueryParams::parse(Some(
+            "pagination.key=AQI%3D&pagination.limit=10&pagination.count_total=true",
+        ));
+        assert_eq!(
+            params.pagination().unwrap(),
+            Some(PageRequest {
+                key: vec![1, 2],
+                offset: 0,
+                limit: 10,
+                count_total: true,
+                reverse: false,
+            })
+        );
+
+        assert_eq!(QueryParams::parse(None).pagination().unwrap(), None);
+
+        let err = QueryParams::parse(Some("pagination.limit=ten"))
+            .pagination()
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_sequence_params() {
+        let params = QueryParams::parse(Some(
+            "packet_commitment_sequences=1&packet_commitment_sequences=2,3",
+        ));
+        assert_eq!(
+            params.u64_list("packet_commitment_sequences").unwrap(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            parse_u64_list("4,5", "packet_ack_sequences").unwrap(),
+            vec![4, 5]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use ibc::core::ics24_host::identifier::ClientId;
+use ibc::core::ics24_host::path::ClientStatePath;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+use crate::types::StoreHeight;
+use crate::AsyncIbcStore;
+
+/// The path prefix of the `grpc.health.v1.Health` methods.
+const HEALTH_PATH_PREFIX: &str = "/grpc.health.v1.Health/";
+
+/// Whether `req` calls the health service. Load balancers and orchestrators
+/// probe it without credentials, so API keys and rate limits do not apply to
+/// it. A client CA does: the TLS handshake requires a certificate before any
+/// request is read.
+pub(crate) fn is_health_check<B>(req: &http::Request<B>) -> bool {
+    req.uri().path().starts_with(HEALTH_PATH_PREFIX)
+}
+
+/// How the `grpc.health.v1.Health` service derives its status from the store.
+/// The services are `NOT_SERVING` while the height has not advanced for
+/// `stale_after`, or while a probe read fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub(crate) stale_after: Duration,
+    pub(crate) probe_interval: Duration,
+}
+
+impl HealthCheck {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            stale_after,
+            probe_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// How often the store is probed, every 5 seconds by default.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+}
+
+/// Tracks when the store height last advanced.
+#[derive(Clone, Copy, Debug)]
+struct Freshness {
+    height: u64,
+    advanced_at: Instant,
+}
+
+impl Freshness {
+    /// Records the current `height` and returns whether the store is still
+    /// fresh at `now`.
+    fn observe(&mut self, height: u64, now: Instant, stale_after: Duration) -> bool {
+        if height > self.height {
+            self.height = height;
+            self.advanced_at = now;
+        }
+        now.saturating_duration_since(self.advanced_at) <= stale_after
+    }
+}
+
+/// Marks `services` and the server as a whole as serving.
+pub(crate) async fn set_serving(reporter: &mut HealthReporter, services: &[&'static str]) {
+    set_status(reporter, services, ServingStatus::Serving).await;
+}
+
+async fn set_status(
+    reporter: &mut HealthReporter,
+    services: &[&'static str],
+    status: ServingStatus,
+) {
+    reporter.set_service_status("", status).await;
+    for service in services {
+        reporter.set_service_status(service, status).await;
+    }
+}
+
+/// Probes `store` forever, updating the health of `services` whenever it
+/// changes.
+pub(crate) async fn monitor<Store: AsyncIbcStore>(
+    store: Arc<Store>,
+    check: HealthCheck,
+    mut reporter: HealthReporter,
+    services: Vec<&'static str>,
+) {
+    let mut freshness = Freshness {
+        height: store.current_height().await,
+        advanced_at: Instant::now(),
+    };
+    let probe_path = ClientStatePath(ClientId::default());
+    let mut interval = tokio::time::interval(check.probe_interval);
+    let mut serving = None;
+
+    loop {
+        interval.tick().await;
+
+        let height = store.current_height().await;
+        let fresh = freshness.observe(height, Instant::now(), check.stale_after);
+        if !fresh {
+            log::warn!("store height {} is stale", height);
+        }
+        let probed = match store
+            .get_client_state(StoreHeight::Latest, &probe_path)
+            .await
+        {
+            Ok(_) => true,
+            Err(e) => {
+                log::warn!("store probe failed: {}", e);
+                false
+            }
+        };
+
+        let healthy = fresh && probed;
+        if serving != Some(healthy) {
+            let status = if healthy {
+                ServingStatus::Serving
+            } else {
+                ServingStatus::NotServing
+            };
+            log::info!("health status changes to {:?}", status);
+            set_status(&mut reporter, &services, status).await;
+            serving = Some(healthy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_freshness() {
+        let start = Instant::now();
+        let stale_after = Duration::from_secs(10);
+        let mut freshness = Freshness {
+            height: 5,
+            advanced_at: start,
+        };
+
+        assert!(freshness.observe(5, start + Duration::from_secs(10), stale_after));
+        assert!(!freshness.observe(5, start + Duration::from_secs(11), stale_after));
+        assert!(freshness.observe(6, start + Duration::from_secs(12), stale_after));
+        assert!(freshness.observe(6, start + Duration::from_secs(20), stale_after));
+        assert!(!freshness.observe(6, start + Duration::from_secs(23), stale_after));
+    }
+}
@@ -0,0 +1,139 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ibc::core::ics24_host::identifier::ConnectionId;
+use ibc_proto::ibc::applications::interchain_accounts::{
+    controller::v1::{
+        query_server::Query as ControllerQuery, Params as ControllerParams,
+        QueryInterchainAccountRequest, QueryInterchainAccountResponse,
+        QueryParamsRequest as ControllerParamsRequest,
+        QueryParamsResponse as ControllerParamsResponse,
+    },
+    host::v1::{
+        query_server::Query as HostQuery, Params as HostParams,
+        QueryParamsRequest as HostParamsRequest, QueryParamsResponse as HostParamsResponse,
+    },
+};
+use tonic::{Request, Response, Status};
+
+use crate::instrument::timed_blocking;
+use crate::service::invalid_field;
+use crate::telemetry::record_span_field;
+use crate::types::{IcaControllerParams, IcaHostParams};
+use crate::{ServerError, StoreHeight};
+
+/// The ICS-27 interchain accounts state of the chain, queried by the
+/// controller and host query services. It is an extension of
+/// [`IbcStore`](crate::IbcStore), given to
+/// [`IbcGrpcServiceBuilder::ica_store`](crate::IbcGrpcServiceBuilder::ica_store)
+/// by the chains running the interchain accounts modules. A chain running
+/// only one of them fails the getters of the other with `Unimplemented`.
+/// Calls run on the blocking pool.
+pub trait IcaStore: Sync + Send {
+    /// Returns the address of the interchain account registered by `owner`
+    /// over `connection_id`.
+    fn get_interchain_account(
+        &self,
+        height: StoreHeight,
+        owner: &str,
+        connection_id: &ConnectionId,
+    ) -> crate::Result<Option<String>>;
+
+    fn get_ica_controller_params(&self, height: StoreHeight) -> crate::Result<IcaControllerParams>;
+
+    fn get_ica_host_params(&self, height: StoreHeight) -> crate::Result<IcaHostParams>;
+}
+
+/// An interchain accounts store shared by the ICA query services.
+#[derive(Clone)]
+pub(crate) struct SharedIcaStore(pub(crate) Arc<dyn IcaStore>);
+
+impl std::fmt::Debug for SharedIcaStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedIcaStore")
+    }
+}
+
+/// Serves `ibc.applications.interchain_accounts.controller.v1.Query` from an
+/// [`IcaStore`].
+pub struct IbcIcaControllerService {
+    store: Arc<dyn IcaStore>,
+}
+
+impl IbcIcaControllerService {
+    pub(crate) fn new(store: SharedIcaStore) -> Self {
+        Self { store: store.0 }
+    }
+}
+
+#[tonic::async_trait]
+impl ControllerQuery for IbcIcaControllerService {
+    async fn interchain_account(
+        &self,
+        request: Request<QueryInterchainAccountRequest>,
+    ) -> Result<Response<QueryInterchainAccountResponse>, Status> {
+        let owner = request.get_ref().owner.trim();
+        if owner.is_empty() {
+            return Err(ServerError::InvalidArgument("empty owner".to_owned())
+                .into_status_with([("field", "owner".to_owned())]));
+        }
+        let connection_id = ConnectionId::from_str(&request.get_ref().connection_id)
+            .map_err(invalid_field("connection_id"))?;
+        record_span_field("connection_id", &connection_id);
+
+        let not_found = format!("interchain account of {} on {}", owner, connection_id);
+        let owner = owner.to_owned();
+        let address = timed_blocking(&self.store, "get_interchain_account", move |store| {
+            store.get_interchain_account(StoreHeight::Latest, &owner, &connection_id)
+        })
+        .await?
+        .ok_or(ServerError::NotFound(not_found))?;
+        Ok(Response::new(QueryInterchainAccountResponse { address }))
+    }
+
+    async fn params(
+        &self,
+        _request: Request<ControllerParamsRequest>,
+    ) -> Result<Response<ControllerParamsResponse>, Status> {
+        let params = timed_blocking(&self.store, "get_ica_controller_params", |store| {
+            store.get_ica_controller_params(StoreHeight::Latest)
+        })
+        .await?;
+        Ok(Response::new(ControllerParamsResponse {
+            params: Some(ControllerParams {
+                controller_enabled: params.controller_enabled,
+            }),
+        }))
+    }
+}
+
+/// Serves `ibc.applications.interchain_accounts.host.v1.Query` from an
+/// [`IcaStore`].
+pub struct IbcIcaHostService {
+    store: Arc<dyn IcaStore>,
+}
+
+impl IbcIcaHostService {
+    pub(crate) fn new(store: SharedIcaStore) -> Self {
+        Self { store: store.0 }
+    }
+}
+
+#[tonic::async_trait]
+impl HostQuery for IbcIcaHostService {
+    async fn params(
+        &self,
+        _request: Request<HostParamsRequest>,
+    ) -> Result<Response<HostParamsResponse>, Status> {
+        let params = timed_blocking(&self.store, "get_ica_host_params", |store| {
+            store.get_ica_host_params(StoreHeight::Latest)
+        })
+        .await?;
+        Ok(Response::new(HostParamsResponse {
+            params: Some(HostParams {
+                host_enabled: params.host_enabled,
+                allow_messages: params.allow_messages,
+            }),
+        }))
+    }
+}
@@ -0,0 +1,254 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use ibc::core::ics02_client::{client_state::ClientState, consensus_state::ConsensusState};
+use ibc::core::ics03_connection::connection::ConnectionEnd;
+use ibc::core::ics04_channel::channel::ChannelEnd;
+use ibc::core::ics04_channel::commitment::{AcknowledgementCommitment, PacketCommitment};
+use ibc::core::ics24_host::identifier::ConnectionId;
+use ibc::core::ics24_host::path::{
+    AcksPath, ChannelEndsPath, ClientConnectionsPath, ClientConsensusStatePath, ClientStatePath,
+    CommitmentsPath, ConnectionsPath, ReceiptsPath,
+};
+
+use tracing::field::Empty;
+use tracing::Instrument;
+
+use crate::catch_panic;
+use crate::metrics::METRICS;
+use crate::types::{Path, RawQuery, StoreHeight};
+use crate::{AsyncIbcStore, Result};
+
+/// Runs `call` with `host` on the blocking pool, recording it like the calls
+/// of the store are. Serves the synchronous extension traits of the host,
+/// e.g. the fee store.
+pub(crate) async fn timed_blocking<Host, T, F>(
+    host: &Arc<Host>,
+    method: &'static str,
+    call: F,
+) -> Result<T>
+where
+    Host: ?Sized + Send + Sync + 'static,
+    T: Send + 'static,
+    F: FnOnce(&Host) -> Result<T> + Send + 'static,
+{
+    let host = Arc::clone(host);
+    let start = Instant::now();
+    let result = catch_panic::spawn_blocking(move || call(&host))
+        .instrument(tracing::info_span!("ibc_store", method))
+        .await;
+    METRICS.observe_store_call(method, start.elapsed(), result.is_ok());
+    result
+}
+
+/// Wraps the store of the gRPC handlers to record the latency and failures of
+/// every call, and to run it in a span child of the request span.
+pub(crate) struct InstrumentedStore<Store> {
+    inner: Arc<Store>,
+}
+
+impl<Store: AsyncIbcStore> InstrumentedStore<Store> {
+    pub(crate) fn new(inner: Arc<Store>) -> Self {
+        Self { inner }
+    }
+
+    async fn timed<T>(
+        &self,
+        method: &'static str,
+        height: Option<StoreHeight>,
+        call: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let span = tracing::info_span!("ibc_store", method, height = Empty);
+        // Only explicit heights are recorded: the height `Latest` resolves to
+        // is only known to the store.
+        if let Some(StoreHeight::Stable(height)) = height {
+            tracing::Span::current().record("height", height);
+            span.record("height", height);
+        }
+
+        let start = Instant::now();
+        let result = call.instrument(span).await;
+        METRICS.observe_store_call(method, start.elapsed(), result.is_ok());
+        result
+    }
+}
+
+#[tonic::async_trait]
+impl<Store: AsyncIbcStore> AsyncIbcStore for InstrumentedStore<Store> {
+    async fn get_client_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientStatePath,
+    ) -> Result<Option<Box<dyn ClientState>>> {
+        self.timed(
+            "get_client_state",
+            Some(height),
+            self.inner.get_client_state(height, path),
+        )
+        .await
+    }
+
+    async fn get_consensus_state(
+        &self,
+        height: StoreHeight,
+        path: &ClientConsensusStatePath,
+    ) -> Result<Option<Box<dyn ConsensusState>>> {
+        self.timed(
+            "get_consensus_state",
+            Some(height),
+            self.inner.get_consensus_state(height, path),
+        )
+        .await
+    }
+
+    async fn get_connection_end(
+        &self,
+        height: StoreHeight,
+        path: &ConnectionsPath,
+    ) -> Result<Option<ConnectionEnd>> {
+        self.timed(
+            "get_connection_end",
+            Some(height),
+            self.inner.get_connection_end(height, path),
+        )
+        .await
+    }
+
+    async fn get_connection_ids(
+        &self,
+        height: StoreHeight,
+        path: &ClientConnectionsPath,
+    ) -> Result<Vec<ConnectionId>> {
+        self.timed(
+            "get_connection_ids",
+            Some(height),
+            self.inner.get_connection_ids(height, path),
+        )
+        .await
+    }
+
+    async fn get_acknowledgement_commitment(
+        &self,
+        height: StoreHeight,
+        path: &AcksPath,
+    ) -> Result<Option<AcknowledgementCommitment>> {
+        self.timed(
+            "get_acknowledgement_commitment",
+            Some(height),
+            self.inner.get_acknowledgement_commitment(height, path),
+        )
+        .await
+    }
+
+    async fn get_acknowledgement_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[AcksPath],
+    ) -> Result<Vec<Option<AcknowledgementCommitment>>> {
+        self.timed(
+            "get_acknowledgement_commitments",
+            Some(height),
+            self.inner.get_acknowledgement_commitments(height, paths),
+        )
+        .await
+    }
+
+    async fn get_channel_end(
+        &self,
+        height: StoreHeight,
+        path: &ChannelEndsPath,
+    ) -> Result<Option<ChannelEnd>> {
+        self.timed(
+            "get_channel_end",
+            Some(height),
+            self.inner.get_channel_end(height, path),
+        )
+        .await
+    }
+
+    async fn get_channel_paths_by_connection(
+        &self,
+        height: StoreHeight,
+        conn_id: &ConnectionId,
+    ) -> Result<Option<Vec<ChannelEndsPath>>> {
+        self.timed(
+            "get_channel_paths_by_connection",
+            Some(height),
+            self.inner.get_channel_paths_by_connection(height, conn_id),
+        )
+        .await
+    }
+
+    async fn get_opt(&self, height: StoreHeight, path: &ReceiptsPath) -> Result<Option<()>> {
+        self.timed("get_opt", Some(height), self.inner.get_opt(height, path))
+            .await
+    }
+
+    async fn get_opts(
+        &self,
+        height: StoreHeight,
+        paths: &[ReceiptsPath],
+    ) -> Result<Vec<Option<()>>> {
+        self.timed("get_opts", Some(height), self.inner.get_opts(height, paths))
+            .await
+    }
+
+    async fn get_packet_commitment(
+        &self,
+        height: StoreHeight,
+        path: &CommitmentsPath,
+    ) -> Result<Option<PacketCommitment>> {
+        self.timed(
+            "get_packet_commitment",
+            Some(height),
+            self.inner.get_packet_commitment(height, path),
+        )
+        .await
+    }
+
+    async fn get_packet_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[CommitmentsPath],
+    ) -> Result<Vec<Option<PacketCommitment>>> {
+        self.timed(
+            "get_packet_commitments",
+            Some(height),
+            self.inner.get_packet_commitments(height, paths),
+        )
+        .await
+    }
+
+    async fn get_paths_by_prefix(&self, key_prefix: &Path) -> Result<Vec<Path>> {
+        let paths = self
+            .timed(
+                "get_paths_by_prefix",
+                None,
+                self.inner.get_paths_by_prefix(key_prefix),
+            )
+            .await?;
+        let prefix = key_prefix.to_string();
+        let segment = prefix.split('/').next().unwrap_or_default();
+        METRICS.observe_paths_scanned(segment, paths.len());
+        Ok(paths)
+    }
+
+    async fn current_height(&self) -> u64 {
+        self.inner.current_height().await
+    }
+
+    async fn get_raw(&self, height: StoreHeight, path: &Path) -> Result<Option<Vec<u8>>> {
+        self.timed("get_raw", Some(height), self.inner.get_raw(height, path))
+            .await
+    }
+
+    async fn query_raw(&self, height: StoreHeight, path: &Path, prove: bool) -> Result<RawQuery> {
+        self.timed(
+            "query_raw",
+            Some(height),
+            self.inner.query_raw(height, path, prove),
+        )
+        .await
+    }
+}
@@ -0,0 +1,705 @@
+//! The proto3 JSON mapping of the IBC query responses, as rendered by the
+//! Cosmos SDK REST gateway: original field names, defaults included, 64-bit
+//! integers as strings, bytes as base64 and enums by name.
+//!
+//! The mapping is generated by `build.rs` from the vendored query protos, into
+//! messages of its own: a response is re-encoded into its generated twin and
+//! serialized from there. As in canonical proto3 JSON, unset message fields
+//! are left out.
+
+use std::fmt;
+
+use prost::Message;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::ServerError;
+
+macro_rules! include_package {
+    ($package:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/json/", $package, ".rs"));
+        include!(concat!(env!("OUT_DIR"), "/json/", $package, ".serde.rs"));
+    };
+}
+
+/// The messages generated with the JSON mapping, by proto package.
+#[allow(dead_code, clippy::all)]
+mod proto {
+    pub(crate) mod cosmos {
+        pub(crate) mod base {
+            pub(crate) mod query {
+                pub(crate) mod v1beta1 {
+                    include_package!("cosmos.base.query.v1beta1");
+                }
+            }
+        }
+    }
+
+    pub(crate) mod ibc {
+        pub(crate) mod core {
+            pub(crate) mod client {
+                pub(crate) mod v1 {
+                    include_package!("ibc.core.client.v1");
+                }
+            }
+            pub(crate) mod commitment {
+                pub(crate) mod v1 {
+                    include_package!("ibc.core.commitment.v1");
+                }
+            }
+            pub(crate) mod connection {
+                pub(crate) mod v1 {
+                    include_package!("ibc.core.connection.v1");
+                }
+            }
+            pub(crate) mod channel {
+                pub(crate) mod v1 {
+                    include_package!("ibc.core.channel.v1");
+                }
+            }
+        }
+    }
+}
+
+/// A message the gateway renders as JSON, through its generated twin.
+pub(crate) trait ToJson: Message + Sized {
+    /// The generated message with the JSON mapping.
+    type Json: Message + Default + Serialize;
+
+    /// Fails on values the mapping has no JSON for, e.g. an unknown enum
+    /// variant.
+    fn to_json(&self) -> crate::Result<Value> {
+        let message = Self::Json::decode(self.encode_to_vec().as_slice())
+            .map_err(|e| ServerError::Decode(e.to_string()))?;
+        serde_json::to_value(message).map_err(|e| ServerError::Decode(e.to_string()))
+    }
+}
+
+macro_rules! to_json {
+    ($($package:ident::$version:ident { $($message:ident),* $(,)? })*) => {
+        $($(
+            impl ToJson for ibc_proto::ibc::core::$package::$version::$message {
+                type Json = proto::ibc::core::$package::$version::$message;
+            }
+        )*)*
+    };
+}
+
+to_json! {
+    client::v1 {
+        QueryClientStateResponse,
+        QueryClientStatesResponse,
+        QueryConsensusStateResponse,
+        QueryConsensusStatesResponse,
+        QueryConsensusStateHeightsResponse,
+        QueryClientStatusResponse,
+        QueryClientParamsResponse,
+        QueryUpgradedClientStateResponse,
+        QueryUpgradedConsensusStateResponse,
+    }
+    connection::v1 {
+        QueryConnectionResponse,
+        QueryConnectionsResponse,
+        QueryClientConnectionsResponse,
+        QueryConnectionClientStateResponse,
+        QueryConnectionConsensusStateResponse,
+    }
+    channel::v1 {
+        QueryChannelResponse,
+        QueryChannelsResponse,
+        QueryConnectionChannelsResponse,
+        QueryChannelClientStateResponse,
+        QueryChannelConsensusStateResponse,
+        QueryPacketCommitmentResponse,
+        QueryPacketCommitmentsResponse,
+        QueryPacketReceiptResponse,
+        QueryPacketAcknowledgementResponse,
+        QueryPacketAcknowledgementsResponse,
+        QueryUnreceivedPacketsResponse,
+        QueryUnreceivedAcksResponse,
+        QueryNextSequenceReceiveResponse,
+    }
+}
+
+/// The `google.protobuf.Any` of the generated messages. Unknown types can't
+/// be expanded into their fields, so the encoded value is kept next to the
+/// `@type`.
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct Any {
+    #[prost(string, tag = "1")]
+    pub(crate) type_url: String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub(crate) value: Vec<u8>,
+}
+
+impl Serialize for Any {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut any = serializer.serialize_struct("Any", 2)?;
+        any.serialize_field("@type", &self.type_url)?;
+        any.serialize_field("value", &base64::encode(&self.value))?;
+        any.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Any {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AnyVisitor;
+
+        impl<'de> Visitor<'de> for AnyVisitor {
+            type Value = Any;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a google.protobuf.Any")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Any, A::Error> {
+                let mut any = Any::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "@type" => any.type_url = map.next_value()?,
+                        "value" => {
+                            let value: String = map.next_value()?;
+                            any.value = base64::decode(value).map_err(de::Error::custom)?;
+                        }
+                        _ => return Err(de::Error::unknown_field(&key, &["@type", "value"])),
+                    }
+                }
+                Ok(any)
+            }
+        }
+
+        deserializer.deserialize_map(AnyVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use ibc_proto::cosmos::base::query::v1beta1::PageResponse;
+    use ibc_proto::google::protobuf::Any;
+    use ibc_proto::ibc::core::channel::v1::{
+        Channel, Counterparty as ChannelCounterparty, IdentifiedChannel, Order, PacketState,
+        QueryChannelClientStateResponse, QueryChannelConsensusStateResponse, QueryChannelResponse,
+        QueryChannelsResponse, QueryConnectionChannelsResponse, QueryNextSequenceReceiveResponse,
+        QueryPacketAcknowledgementResponse, QueryPacketAcknowledgementsResponse,
+        QueryPacketCommitmentResponse, QueryPacketCommitmentsResponse, QueryPacketReceiptResponse,
+        QueryUnreceivedAcksResponse, QueryUnreceivedPacketsResponse, State as ChannelState,
+    };
+    use ibc_proto::ibc::core::client::v1::{
+        ConsensusStateWithHeight, Height, IdentifiedClientState, Params, QueryClientParamsResponse,
+        QueryClientStateResponse, QueryClientStatesResponse, QueryClientStatusResponse,
+        QueryConsensusStateHeightsResponse, QueryConsensusStateResponse,
+        QueryConsensusStatesResponse, QueryUpgradedClientStateResponse,
+        QueryUpgradedConsensusStateResponse,
+    };
+    use ibc_proto::ibc::core::commitment::v1::MerklePrefix;
+    use ibc_proto::ibc::core::connection::v1::{
+        ConnectionEnd, Counterparty as ConnectionCounterparty, IdentifiedConnection,
+        QueryClientConnectionsResponse, QueryConnectionClientStateResponse,
+        QueryConnectionConsensusStateResponse, QueryConnectionResponse, QueryConnectionsResponse,
+        State as ConnectionState, Version,
+    };
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Asserts that `message` renders as `expected`, and that `expected`
+    /// parses back into `message`.
+    fn assert_json<T>(message: T, expected: Value)
+    where
+        T: ToJson + Default + PartialEq + Debug,
+        T::Json: DeserializeOwned,
+    {
+        assert_eq!(message.to_json().unwrap(), expected);
+        let parsed: T::Json = serde_json::from_value(expected).unwrap();
+        assert_eq!(
+            T::decode(parsed.encode_to_vec().as_slice()).unwrap(),
+            message
+        );
+    }
+
+    fn height() -> Height {
+        Height {
+            revision_number: 1,
+            revision_height: 42,
+        }
+    }
+
+    fn height_json() -> Value {
+        json!({ "revision_number": "1", "revision_height": "42" })
+    }
+
+    fn any() -> Any {
+        Any {
+            type_url: "/ibc.lightclients.tendermint.v1.ClientState".to_owned(),
+            value: vec![1, 2, 3],
+        }
+    }
+
+    fn any_json() -> Value {
+        json!({ "@type": "/ibc.lightclients.tendermint.v1.ClientState", "value": "AQID" })
+    }
+
+    fn page() -> Option<PageResponse> {
+        Some(PageResponse {
+            next_key: vec![0xff],
+            total: 2,
+        })
+    }
+
+    fn page_json() -> Value {
+        json!({ "next_key": "/w==", "total": "2" })
+    }
+
+    const PROOF: &[u8] = &[4, 5, 6];
+    const PROOF_JSON: &str = "BAUG";
+
+    #[test]
+    fn test_client_responses_json() {
+        assert_json(
+            QueryClientStateResponse {
+                client_state: Some(any()),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "client_state": any_json(),
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryClientStatesResponse {
+                client_states: vec![IdentifiedClientState {
+                    client_id: "07-tendermint-0".to_owned(),
+                    client_state: Some(any()),
+                }],
+                pagination: page(),
+            },
+            json!({
+                "client_states": [{ "client_id": "07-tendermint-0", "client_state": any_json() }],
+                "pagination": page_json(),
+            }),
+        );
+        assert_json(
+            QueryConsensusStateResponse {
+                consensus_state: Some(any()),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "consensus_state": any_json(),
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryConsensusStatesResponse {
+                consensus_states: vec![ConsensusStateWithHeight {
+                    height: Some(height()),
+                    consensus_state: Some(any()),
+                }],
+                pagination: page(),
+            },
+            json!({
+                "consensus_states": [{ "height": height_json(), "consensus_state": any_json() }],
+                "pagination": page_json(),
+            }),
+        );
+        assert_json(
+            QueryConsensusStateHeightsResponse {
+                consensus_state_heights: vec![height()],
+                pagination: page(),
+            },
+            json!({
+                "consensus_state_heights": [height_json()],
+                "pagination": page_json(),
+            }),
+        );
+        assert_json(
+            QueryClientStatusResponse {
+                status: "Active".to_owned(),
+            },
+            json!({ "status": "Active" }),
+        );
+        assert_json(
+            QueryClientParamsResponse {
+                params: Some(Params {
+                    allowed_clients: vec!["07-tendermint".to_owned()],
+                }),
+            },
+            json!({ "params": { "allowed_clients": ["07-tendermint"] } }),
+        );
+        assert_json(
+            QueryUpgradedClientStateResponse {
+                upgraded_client_state: Some(any()),
+            },
+            json!({ "upgraded_client_state": any_json() }),
+        );
+        assert_json(
+            QueryUpgradedConsensusStateResponse {
+                upgraded_consensus_state: Some(any()),
+            },
+            json!({ "upgraded_consensus_state": any_json() }),
+        );
+    }
+
+    fn connection_end() -> ConnectionEnd {
+        ConnectionEnd {
+            client_id: "07-tendermint-0".to_owned(),
+            versions: vec![Version {
+                identifier: "1".to_owned(),
+                features: vec!["ORDER_ORDERED".to_owned(), "ORDER_UNORDERED".to_owned()],
+            }],
+            state: ConnectionState::Open as i32,
+            counterparty: Some(ConnectionCounterparty {
+                client_id: "07-tendermint-1".to_owned(),
+                connection_id: "connection-1".to_owned(),
+                prefix: Some(MerklePrefix {
+                    key_prefix: b"ibc".to_vec(),
+                }),
+            }),
+            delay_period: 10,
+        }
+    }
+
+    fn connection_end_json() -> Value {
+        json!({
+            "client_id": "07-tendermint-0",
+            "versions": [{
+                "identifier": "1",
+                "features": ["ORDER_ORDERED", "ORDER_UNORDERED"],
+            }],
+            "state": "STATE_OPEN",
+            "counterparty": {
+                "client_id": "07-tendermint-1",
+                "connection_id": "connection-1",
+                "prefix": { "key_prefix": "aWJj" },
+            },
+            "delay_period": "10",
+        })
+    }
+
+    fn identified_client_state_json() -> Value {
+        json!({ "client_id": "07-tendermint-0", "client_state": any_json() })
+    }
+
+    fn identified_client_state() -> IdentifiedClientState {
+        IdentifiedClientState {
+            client_id: "07-tendermint-0".to_owned(),
+            client_state: Some(any()),
+        }
+    }
+
+    #[test]
+    fn test_connection_responses_json() {
+        assert_json(
+            QueryConnectionResponse {
+                connection: Some(connection_end()),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "connection": connection_end_json(),
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+
+        let end = connection_end();
+        let mut connection_json = connection_end_json();
+        connection_json["id"] = json!("connection-0");
+        assert_json(
+            QueryConnectionsResponse {
+                connections: vec![IdentifiedConnection {
+                    id: "connection-0".to_owned(),
+                    client_id: end.client_id,
+                    versions: end.versions,
+                    state: end.state,
+                    counterparty: end.counterparty,
+                    delay_period: end.delay_period,
+                }],
+                pagination: page(),
+                height: Some(height()),
+            },
+            json!({
+                "connections": [connection_json],
+                "pagination": page_json(),
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryClientConnectionsResponse {
+                connection_paths: vec!["connection-0".to_owned()],
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "connection_paths": ["connection-0"],
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryConnectionClientStateResponse {
+                identified_client_state: Some(identified_client_state()),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "identified_client_state": identified_client_state_json(),
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryConnectionConsensusStateResponse {
+                consensus_state: Some(any()),
+                client_id: "07-tendermint-0".to_owned(),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "consensus_state": any_json(),
+                "client_id": "07-tendermint-0",
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+    }
+
+    fn identified_channel() -> IdentifiedChannel {
+        IdentifiedChannel {
+            state: ChannelState::Open as i32,
+            ordering: Order::Unordered as i32,
+            counterparty: Some(ChannelCounterparty {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-1".to_owned(),
+            }),
+            connection_hops: vec!["connection-0".to_owned()],
+            version: "ics20-1".to_owned(),
+            port_id: "transfer".to_owned(),
+            channel_id: "channel-0".to_owned(),
+        }
+    }
+
+    fn identified_channel_json() -> Value {
+        json!({
+            "state": "STATE_OPEN",
+            "ordering": "ORDER_UNORDERED",
+            "counterparty": { "port_id": "transfer", "channel_id": "channel-1" },
+            "connection_hops": ["connection-0"],
+            "version": "ics20-1",
+            "port_id": "transfer",
+            "channel_id": "channel-0",
+        })
+    }
+
+    fn packet_state() -> PacketState {
+        PacketState {
+            port_id: "transfer".to_owned(),
+            channel_id: "channel-0".to_owned(),
+            sequence: 3,
+            data: vec![7, 8, 9],
+        }
+    }
+
+    fn packet_state_json() -> Value {
+        json!({
+            "port_id": "transfer",
+            "channel_id": "channel-0",
+            "sequence": "3",
+            "data": "BwgJ",
+        })
+    }
+
+    #[test]
+    fn test_channel_responses_json() {
+        let channel = identified_channel();
+        let mut channel_json = identified_channel_json();
+        channel_json.as_object_mut().unwrap().remove("port_id");
+        channel_json.as_object_mut().unwrap().remove("channel_id");
+        assert_json(
+            QueryChannelResponse {
+                channel: Some(Channel {
+                    state: channel.state,
+                    ordering: channel.ordering,
+                    counterparty: channel.counterparty,
+                    connection_hops: channel.connection_hops,
+                    version: channel.version,
+                }),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "channel": channel_json,
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryChannelsResponse {
+                channels: vec![identified_channel()],
+                pagination: page(),
+                height: Some(height()),
+            },
+            json!({
+                "channels": [identified_channel_json()],
+                "pagination": page_json(),
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryConnectionChannelsResponse {
+                channels: vec![identified_channel()],
+                pagination: page(),
+                height: Some(height()),
+            },
+            json!({
+                "channels": [identified_channel_json()],
+                "pagination": page_json(),
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryChannelClientStateResponse {
+                identified_client_state: Some(identified_client_state()),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "identified_client_state": identified_client_state_json(),
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryChannelConsensusStateResponse {
+                consensus_state: Some(any()),
+                client_id: "07-tendermint-0".to_owned(),
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "consensus_state": any_json(),
+                "client_id": "07-tendermint-0",
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryPacketCommitmentResponse {
+                commitment: vec![1, 2, 3],
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "commitment": "AQID",
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryPacketCommitmentsResponse {
+                commitments: vec![packet_state()],
+                pagination: page(),
+                height: Some(height()),
+            },
+            json!({
+                "commitments": [packet_state_json()],
+                "pagination": page_json(),
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryPacketReceiptResponse {
+                received: true,
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "received": true,
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryPacketAcknowledgementResponse {
+                acknowledgement: vec![1, 2, 3],
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "acknowledgement": "AQID",
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryPacketAcknowledgementsResponse {
+                acknowledgements: vec![packet_state()],
+                pagination: page(),
+                height: Some(height()),
+            },
+            json!({
+                "acknowledgements": [packet_state_json()],
+                "pagination": page_json(),
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryUnreceivedPacketsResponse {
+                sequences: vec![1, u64::MAX],
+                height: Some(height()),
+            },
+            json!({
+                "sequences": ["1", "18446744073709551615"],
+                "height": height_json(),
+            }),
+        );
+        assert_json(
+            QueryUnreceivedAcksResponse {
+                sequences: vec![2],
+                height: Some(height()),
+            },
+            json!({ "sequences": ["2"], "height": height_json() }),
+        );
+        assert_json(
+            QueryNextSequenceReceiveResponse {
+                next_sequence_receive: 5,
+                proof: PROOF.to_vec(),
+                proof_height: Some(height()),
+            },
+            json!({
+                "next_sequence_receive": "5",
+                "proof": PROOF_JSON,
+                "proof_height": height_json(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_defaults_json() {
+        assert_json(
+            QueryPacketCommitmentResponse::default(),
+            json!({ "commitment": "", "proof": "" }),
+        );
+        assert_json(QueryChannelsResponse::default(), json!({ "channels": [] }));
+    }
+
+    #[test]
+    fn test_unknown_enum_json() {
+        let response = QueryChannelsResponse {
+            channels: vec![IdentifiedChannel {
+                state: 42,
+                ..identified_channel()
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(response.to_json(), Err(ServerError::Decode(_))));
+    }
+}
@@ -1,6 +1,30 @@
+mod abci;
+mod abci_query;
+mod async_store;
+mod auth;
+mod builder;
+mod catch_panic;
+mod cosmos;
 pub mod error;
+mod fee;
+mod gateway;
+mod health;
+mod ica;
+mod instrument;
+mod json;
+mod limit;
+mod metrics;
+mod mtls;
+mod pagination;
+mod rate_limit;
 mod service;
+mod telemetry;
+mod tendermint;
+mod transfer;
 pub mod types;
+mod web;
+
+use std::fmt::Display;
 
 use ibc::core::ics02_client::{client_state::ClientState, consensus_state::ConsensusState};
 use ibc::core::ics03_connection::connection::ConnectionEnd;
@@ -11,19 +35,51 @@ use ibc::core::ics24_host::path::{
     AcksPath, ChannelEndsPath, ClientConnectionsPath, ClientConsensusStatePath, ClientStatePath,
     CommitmentsPath, ConnectionsPath, ReceiptsPath,
 };
+use ibc_proto::ibc::core::channel::v1::Channel as RawChannel;
+use ibc_proto::ibc::core::connection::v1::{
+    ClientPaths as RawClientPaths, ConnectionEnd as RawConnectionEnd,
+};
+use prost::Message;
 
+pub use crate::async_store::{AsyncIbcStore, BlockingIbcStore};
+pub use crate::auth::{api_key_label, ApiKeyLabel, ApiKeyLayer, ApiKeys};
+pub use crate::builder::IbcGrpcServiceBuilder;
+pub use crate::catch_panic::CatchPanicLayer;
+pub use crate::cosmos::{AuthHost, IbcAuthService, IbcStakingService, StakingHost};
 use crate::error::ServerError;
-use crate::service::IbcGrpcService;
+pub use crate::fee::{IbcFeeService, IbcFeeStore};
+pub use crate::health::HealthCheck;
+pub use crate::ica::{IbcIcaControllerService, IbcIcaHostService, IcaStore};
+pub use crate::metrics::MetricsLayer;
+pub use crate::mtls::{peer_identity, ClientCertAllowList, PeerIdentity};
+pub use crate::rate_limit::RateLimit;
+pub use crate::service::IbcGrpcService;
+pub use crate::telemetry::{
+    install_file_tracer, install_stdout_tracer, install_tracer, TraceLayer,
+};
+pub use crate::transfer::{IbcTransferService, IbcTransferStore};
+pub use crate::types::{
+    Account, Fee, IcaControllerParams, IcaHostParams, IdentifiedPacketFees, PacketFee, RawQuery,
+    StakingParams, TransferParams,
+};
 use crate::types::{Path, StoreHeight};
+pub use crate::web::CorsPolicy;
 
 pub type Result<T> = std::result::Result<T, ServerError>;
 
-pub async fn run_ibc_grpc<Store>(store: Store, addr: String)
+pub async fn run_ibc_grpc<Store>(store: Store, addr: String) -> Result<()>
 where
     Store: IbcStore + 'static,
+{
+    run_async_ibc_grpc(BlockingIbcStore::new(store), addr).await
+}
+
+pub async fn run_async_ibc_grpc<Store>(store: Store, addr: String) -> Result<()>
+where
+    Store: AsyncIbcStore + 'static,
 {
     log::info!("ibc start");
-    IbcGrpcService::new(store, addr).run().await;
+    IbcGrpcServiceBuilder::new(addr).build(store)?.run().await
 }
 
 pub trait IbcStore: Sync + Send {
@@ -43,35 +99,293 @@ pub trait IbcStore: Sync + Send {
         &self,
         height: StoreHeight,
         path: &ConnectionsPath,
-    ) -> Result<Option<ConnectionEnd>>;
+    ) -> Result<Option<ConnectionEnd>> {
+        let path = Path::from(path.clone());
+        decode_raw::<_, RawConnectionEnd>(&path, self.get_raw(height, &path)?)
+    }
 
+    /// The default decodes the `ClientPaths` stored by ibc-go.
     fn get_connection_ids(
         &self,
         height: StoreHeight,
         path: &ClientConnectionsPath,
-    ) -> Result<Vec<ConnectionId>>;
+    ) -> Result<Vec<ConnectionId>> {
+        let path = Path::from(path.clone());
+        decode_connection_ids(&path, self.get_raw(height, &path)?)
+    }
 
     fn get_acknowledgement_commitment(
         &self,
         height: StoreHeight,
         path: &AcksPath,
-    ) -> Result<Option<AcknowledgementCommitment>>;
+    ) -> Result<Option<AcknowledgementCommitment>> {
+        Ok(self
+            .get_raw(height, &path.clone().into())?
+            .map(AcknowledgementCommitment::from))
+    }
+
+    /// Batched form of `get_acknowledgement_commitment`. Implementations must
+    /// return exactly one entry per path, in the same order as `paths`.
+    fn get_acknowledgement_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[AcksPath],
+    ) -> Result<Vec<Option<AcknowledgementCommitment>>> {
+        paths
+            .iter()
+            .map(|path| self.get_acknowledgement_commitment(height, path))
+            .collect()
+    }
 
     fn get_channel_end(
         &self,
         height: StoreHeight,
         path: &ChannelEndsPath,
-    ) -> Result<Option<ChannelEnd>>;
+    ) -> Result<Option<ChannelEnd>> {
+        let path = Path::from(path.clone());
+        decode_raw::<_, RawChannel>(&path, self.get_raw(height, &path)?)
+    }
+
+    /// Returns the paths of the channel ends whose first connection hop is
+    /// `conn_id`, for stores that maintain a connection to channels index.
+    /// The default returns `Ok(None)`, which makes the server fall back to
+    /// scanning every channel end.
+    fn get_channel_paths_by_connection(
+        &self,
+        _height: StoreHeight,
+        _conn_id: &ConnectionId,
+    ) -> Result<Option<Vec<ChannelEndsPath>>> {
+        Ok(None)
+    }
 
-    fn get_opt(&self, height: StoreHeight, path: &ReceiptsPath) -> Result<Option<()>>;
+    /// The default treats any value stored under the receipt path as a
+    /// receipt.
+    fn get_opt(&self, height: StoreHeight, path: &ReceiptsPath) -> Result<Option<()>> {
+        Ok(self.get_raw(height, &path.clone().into())?.map(|_| ()))
+    }
+
+    /// Batched form of `get_opt`. Implementations must return exactly one
+    /// entry per path, in the same order as `paths`.
+    fn get_opts(&self, height: StoreHeight, paths: &[ReceiptsPath]) -> Result<Vec<Option<()>>> {
+        paths
+            .iter()
+            .map(|path| self.get_opt(height, path))
+            .collect()
+    }
 
     fn get_packet_commitment(
         &self,
         height: StoreHeight,
         path: &CommitmentsPath,
-    ) -> Result<Option<PacketCommitment>>;
+    ) -> Result<Option<PacketCommitment>> {
+        Ok(self
+            .get_raw(height, &path.clone().into())?
+            .map(PacketCommitment::from))
+    }
+
+    /// Batched form of `get_packet_commitment`. Implementations must return
+    /// exactly one entry per path, in the same order as `paths`.
+    fn get_packet_commitments(
+        &self,
+        height: StoreHeight,
+        paths: &[CommitmentsPath],
+    ) -> Result<Vec<Option<PacketCommitment>>> {
+        paths
+            .iter()
+            .map(|path| self.get_packet_commitment(height, path))
+            .collect()
+    }
 
     fn get_paths_by_prefix(&self, key_prefix: &Path) -> Result<Vec<Path>>;
 
     fn current_height(&self) -> u64;
+
+    /// Returns the raw bytes committed under `path`, or `None` if nothing is
+    /// stored there. The connection, channel and packet getters default to
+    /// decoding its values the way ibc-go encodes them, and other values can
+    /// be read with [`get_decoded`](Self::get_decoded), so a store may
+    /// implement this alone for them. The default fails with `Unimplemented`.
+    fn get_raw(&self, _height: StoreHeight, _path: &Path) -> Result<Option<Vec<u8>>> {
+        Err(ServerError::Unimplemented("raw values".to_owned()))
+    }
+
+    /// Decodes the protobuf encoded value stored under `path` as a `Raw`
+    /// message, then converts it into `T`, e.g. a `ConnectionEnd` from a
+    /// `RawConnectionEnd`.
+    fn get_decoded<T, Raw>(&self, height: StoreHeight, path: &Path) -> Result<Option<T>>
+    where
+        Self: Sized,
+        T: TryFrom<Raw>,
+        T::Error: Display,
+        Raw: Message + Default,
+    {
+        decode_raw(path, self.get_raw(height, path)?)
+    }
+
+    /// Returns the raw bytes committed under `path`, with their ICS-23 proof
+    /// if `prove` is set and the store can prove. It is only called by the
+    /// ABCI query endpoint.
+    ///
+    /// The default answers from [`get_raw`](Self::get_raw), without proofs.
+    fn query_raw(&self, height: StoreHeight, path: &Path, _prove: bool) -> Result<RawQuery> {
+        let height = match height {
+            StoreHeight::Latest => self.current_height(),
+            StoreHeight::Stable(height) => height,
+        };
+        Ok(RawQuery {
+            value: self.get_raw(StoreHeight::Stable(height), path)?,
+            height,
+            proof: None,
+        })
+    }
+}
+
+/// Decodes the protobuf encoded `bytes` read from `path`.
+pub(crate) fn decode_raw<T, Raw>(path: &Path, bytes: Option<Vec<u8>>) -> Result<Option<T>>
+where
+    T: TryFrom<Raw>,
+    T::Error: Display,
+    Raw: Message + Default,
+{
+    let bytes = match bytes {
+        Some(bytes) => bytes,
+        None => return Ok(None),
+    };
+    let raw = Raw::decode(bytes.as_slice())
+        .map_err(|e| ServerError::Decode(format!("{}: {}", path, e)))?;
+    T::try_from(raw)
+        .map(Some)
+        .map_err(|e| ServerError::Decode(format!("{}: {}", path, e)))
+}
+
+/// Decodes the `ClientPaths` ibc-go stores the connection ids of a client in.
+pub(crate) fn decode_connection_ids(
+    path: &Path,
+    bytes: Option<Vec<u8>>,
+) -> Result<Vec<ConnectionId>> {
+    let paths = match bytes {
+        Some(bytes) => {
+            RawClientPaths::decode(bytes.as_slice())
+                .map_err(|e| ServerError::Decode(format!("{}: {}", path, e)))?
+                .paths
+        }
+        None => return Ok(vec![]),
+    };
+    paths
+        .iter()
+        .map(|id| {
+            id.parse()
+                .map_err(|e| ServerError::Decode(format!("{}: {}", path, e)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ibc::core::ics04_channel::packet::Sequence;
+    use ibc::core::ics24_host::identifier::{ChannelId, PortId};
+    use ibc_proto::ibc::core::channel::v1::Counterparty as RawCounterparty;
+
+    use super::*;
+
+    /// A store implementing `get_raw` alone for the getters with defaults.
+    struct RawStore(BTreeMap<String, Vec<u8>>);
+
+    impl IbcStore for RawStore {
+        fn get_client_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientStatePath,
+        ) -> Result<Option<Box<dyn ClientState>>> {
+            Ok(None)
+        }
+
+        fn get_consensus_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientConsensusStatePath,
+        ) -> Result<Option<Box<dyn ConsensusState>>> {
+            Ok(None)
+        }
+
+        fn get_paths_by_prefix(&self, _key_prefix: &Path) -> Result<Vec<Path>> {
+            Ok(vec![])
+        }
+
+        fn current_height(&self) -> u64 {
+            9
+        }
+
+        fn get_raw(&self, _height: StoreHeight, path: &Path) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.get(&path.to_string()).cloned())
+        }
+    }
+
+    #[test]
+    fn test_getters_from_raw_values() {
+        let port_id = PortId::transfer();
+        let channel_id = ChannelId::new(0);
+        let channel = RawChannel {
+            state: 3,
+            ordering: 1,
+            counterparty: Some(RawCounterparty {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-1".to_owned(),
+            }),
+            connection_hops: vec!["connection-0".to_owned()],
+            version: "ics20-1".to_owned(),
+        };
+        let channel_path = ChannelEndsPath(port_id.clone(), channel_id.clone());
+        let commitment_path = CommitmentsPath {
+            port_id: port_id.clone(),
+            channel_id: channel_id.clone(),
+            sequence: Sequence::from(1),
+        };
+        let store = RawStore(BTreeMap::from([
+            (channel_path.to_string(), channel.encode_to_vec()),
+            (commitment_path.to_string(), vec![0xab; 32]),
+            (
+                "clients/07-tendermint-0/connections".to_owned(),
+                RawClientPaths {
+                    paths: vec!["connection-0".to_owned()],
+                }
+                .encode_to_vec(),
+            ),
+        ]));
+
+        let channel_end = store
+            .get_channel_end(StoreHeight::Latest, &channel_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(channel_end.connection_hops().len(), 1);
+        let commitment = store
+            .get_packet_commitment(StoreHeight::Latest, &commitment_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(commitment.into_vec(), vec![0xab; 32]);
+        let connection_ids = store
+            .get_connection_ids(
+                StoreHeight::Latest,
+                &ClientConnectionsPath("07-tendermint-0".parse().unwrap()),
+            )
+            .unwrap();
+        assert_eq!(connection_ids, vec![ConnectionId::new(0)]);
+
+        let raw = store
+            .query_raw(StoreHeight::Latest, &Path::from(channel_path), false)
+            .unwrap();
+        assert_eq!(raw.height, 9);
+        assert_eq!(raw.value, Some(channel.encode_to_vec()));
+
+        let corrupt = RawStore(BTreeMap::from([(
+            "channelEnds/ports/transfer/channels/channel-0".to_owned(),
+            vec![0xff],
+        )]));
+        assert!(matches!(
+            corrupt.get_channel_end(StoreHeight::Latest, &ChannelEndsPath(port_id, channel_id)),
+            Err(ServerError::Decode(_))
+        ));
+    }
 }
@@ -0,0 +1,80 @@
+use std::task::{Context, Poll};
+
+use futures::StreamExt;
+use http::Request;
+use hyper::Body;
+use tonic::Status;
+use tower::{Layer, Service};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A layer failing requests whose body grows beyond `max_size` bytes with
+/// `RESOURCE_EXHAUSTED`. All IBC queries are unary, so the body holds exactly
+/// one encoded request message. `None` disables the limit.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestSizeLimitLayer {
+    max_size: Option<usize>,
+}
+
+impl RequestSizeLimitLayer {
+    pub fn new(max_size: Option<usize>) -> Self {
+        Self { max_size }
+    }
+}
+
+impl<S> Layer<S> for RequestSizeLimitLayer {
+    type Service = RequestSizeLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestSizeLimit {
+            inner,
+            max_size: self.max_size,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestSizeLimit<S> {
+    inner: S,
+    max_size: Option<usize>,
+}
+
+impl<S> Service<Request<Body>> for RequestSizeLimit<S>
+where
+    S: Service<Request<Body>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return self.inner.call(req),
+        };
+
+        // tonic looks for a `Status` in the source chain of body errors, so the
+        // request fails with `RESOURCE_EXHAUSTED` rather than `INTERNAL`.
+        let (parts, body) = req.into_parts();
+        let mut received = 0;
+        let limited = body.map(move |chunk| -> Result<_, BoxError> {
+            let chunk = chunk?;
+            received += chunk.len();
+            if received > max_size {
+                return Err(Status::resource_exhausted(format!(
+                    "request is larger than {} bytes",
+                    max_size
+                ))
+                .into());
+            }
+            Ok(chunk)
+        });
+
+        self.inner
+            .call(Request::from_parts(parts, Body::wrap_stream(limited)))
+    }
+}
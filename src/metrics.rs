@@ -0,0 +1,470 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use http::{Request, Response};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Body;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use tonic::body::BoxBody;
+use tonic::Code;
+use tower::{Layer, Service};
+
+use crate::error::ServerError;
+
+/// The metrics of every server of the process.
+pub(crate) static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_errors: IntCounterVec,
+    request_duration: HistogramVec,
+    store_calls: HistogramVec,
+    store_errors: IntCounterVec,
+    paths_scanned: IntCounterVec,
+    api_key_requests: IntCounterVec,
+    caught_panics: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new("ibc_grpc_requests_total", "gRPC requests by method"),
+            &["method"],
+        )
+        .expect("valid metric");
+        let request_errors = IntCounterVec::new(
+            Opts::new(
+                "ibc_grpc_request_errors_total",
+                "Failed gRPC requests by method and status code",
+            ),
+            &["method", "code"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "ibc_grpc_request_duration_seconds",
+                "gRPC request latency by method",
+            ),
+            &["method"],
+        )
+        .expect("valid metric");
+        let store_calls = HistogramVec::new(
+            HistogramOpts::new(
+                "ibc_store_call_duration_seconds",
+                "IbcStore call latency by method",
+            ),
+            &["method"],
+        )
+        .expect("valid metric");
+        let store_errors = IntCounterVec::new(
+            Opts::new("ibc_store_errors_total", "Failed IbcStore calls by method"),
+            &["method"],
+        )
+        .expect("valid metric");
+        let paths_scanned = IntCounterVec::new(
+            Opts::new(
+                "ibc_store_paths_scanned_total",
+                "Paths returned by prefix scans, by the first prefix segment",
+            ),
+            &["prefix"],
+        )
+        .expect("valid metric");
+        let api_key_requests = IntCounterVec::new(
+            Opts::new(
+                "ibc_grpc_api_key_requests_total",
+                "Authenticated gRPC requests by API key label",
+            ),
+            &["api_key"],
+        )
+        .expect("valid metric");
+        let caught_panics = IntCounter::new(
+            "ibc_grpc_caught_panics_total",
+            "Handler panics turned into statuses",
+        )
+        .expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests.clone()))
+            .and_then(|_| registry.register(Box::new(request_errors.clone())))
+            .and_then(|_| registry.register(Box::new(request_duration.clone())))
+            .and_then(|_| registry.register(Box::new(store_calls.clone())))
+            .and_then(|_| registry.register(Box::new(store_errors.clone())))
+            .and_then(|_| registry.register(Box::new(paths_scanned.clone())))
+            .and_then(|_| registry.register(Box::new(api_key_requests.clone())))
+            .and_then(|_| registry.register(Box::new(caught_panics.clone())))
+            .expect("metrics registered once");
+
+        Self {
+            registry,
+            requests,
+            request_errors,
+            request_duration,
+            store_calls,
+            store_errors,
+            paths_scanned,
+            api_key_requests,
+            caught_panics,
+        }
+    }
+
+    pub(crate) fn observe_request(&self, method: &str, elapsed: Duration, code: Code) {
+        self.requests.with_label_values(&[method]).inc();
+        self.request_duration
+            .with_label_values(&[method])
+            .observe(elapsed.as_secs_f64());
+        if code != Code::Ok {
+            self.request_errors
+                .with_label_values(&[method, &format!("{:?}", code)])
+                .inc();
+        }
+    }
+
+    pub(crate) fn observe_store_call(&self, method: &str, elapsed: Duration, ok: bool) {
+        self.store_calls
+            .with_label_values(&[method])
+            .observe(elapsed.as_secs_f64());
+        if !ok {
+            self.store_errors.with_label_values(&[method]).inc();
+        }
+    }
+
+    pub(crate) fn observe_paths_scanned(&self, prefix: &str, count: usize) {
+        self.paths_scanned
+            .with_label_values(&[prefix])
+            .inc_by(count as u64);
+    }
+
+    pub(crate) fn observe_api_key(&self, label: &str) {
+        self.api_key_requests.with_label_values(&[label]).inc();
+    }
+
+    pub(crate) fn observe_caught_panic(&self) {
+        self.caught_panics.inc();
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding never fails");
+        buffer
+    }
+}
+
+/// The method label of requests for paths no mounted service serves, which
+/// keeps clients from creating a series per path they make up.
+pub(crate) const UNKNOWN_METHOD: &str = "unknown";
+
+/// The query methods of every service the server can mount.
+const SERVICE_METHODS: &[(&str, &[&str])] = &[
+    ("grpc.health.v1.Health", &["Check", "Watch"]),
+    (
+        "grpc.reflection.v1alpha.ServerReflection",
+        &["ServerReflectionInfo"],
+    ),
+    (
+        "ibc.core.client.v1.Query",
+        &[
+            "ClientState",
+            "ClientStates",
+            "ConsensusState",
+            "ConsensusStates",
+            "ConsensusStateHeights",
+            "ClientStatus",
+            "ClientParams",
+            "UpgradedClientState",
+            "UpgradedConsensusState",
+        ],
+    ),
+    (
+        "ibc.core.connection.v1.Query",
+        &[
+            "Connection",
+            "Connections",
+            "ClientConnections",
+            "ConnectionClientState",
+            "ConnectionConsensusState",
+        ],
+    ),
+    (
+        "ibc.core.channel.v1.Query",
+        &[
+            "Channel",
+            "Channels",
+            "ConnectionChannels",
+            "ChannelClientState",
+            "ChannelConsensusState",
+            "PacketCommitment",
+            "PacketCommitments",
+            "PacketReceipt",
+            "PacketAcknowledgement",
+            "PacketAcknowledgements",
+            "UnreceivedPackets",
+            "UnreceivedAcks",
+            "NextSequenceReceive",
+        ],
+    ),
+    (
+        "ibc.applications.transfer.v1.Query",
+        &[
+            "DenomTrace",
+            "DenomTraces",
+            "Params",
+            "DenomHash",
+            "EscrowAddress",
+        ],
+    ),
+    (
+        "ibc.applications.interchain_accounts.controller.v1.Query",
+        &["InterchainAccount", "Params"],
+    ),
+    (
+        "ibc.applications.interchain_accounts.host.v1.Query",
+        &["Params"],
+    ),
+    (
+        "ibc.applications.fee.v1.Query",
+        &[
+            "IncentivizedPackets",
+            "IncentivizedPacket",
+            "IncentivizedPacketsForChannel",
+            "TotalRecvFees",
+            "TotalAckFees",
+            "TotalTimeoutFees",
+            "Payee",
+            "CounterpartyPayee",
+            "FeeEnabledChannels",
+            "FeeEnabledChannel",
+        ],
+    ),
+    (
+        "cosmos.base.tendermint.v1beta1.Service",
+        &[
+            "GetNodeInfo",
+            "GetSyncing",
+            "GetLatestBlock",
+            "GetBlockByHeight",
+            "GetLatestValidatorSet",
+            "GetValidatorSetByHeight",
+            "ABCIQuery",
+        ],
+    ),
+    (
+        "cosmos.staking.v1beta1.Query",
+        &[
+            "Validators",
+            "Validator",
+            "ValidatorDelegations",
+            "ValidatorUnbondingDelegations",
+            "Delegation",
+            "UnbondingDelegation",
+            "DelegatorDelegations",
+            "DelegatorUnbondingDelegations",
+            "Redelegations",
+            "DelegatorValidators",
+            "DelegatorValidator",
+            "HistoricalInfo",
+            "Pool",
+            "Params",
+        ],
+    ),
+    (
+        "cosmos.auth.v1beta1.Query",
+        &[
+            "Accounts",
+            "Account",
+            "AccountAddressByID",
+            "Params",
+            "ModuleAccounts",
+            "Bech32Prefix",
+            "AddressBytesToString",
+            "AddressStringToBytes",
+        ],
+    ),
+];
+
+/// A layer recording the count, latency and status code of every request.
+/// Requests are labelled with their `/<service>/<method>` path if it is a
+/// method of one of the mounted services, and with `unknown` otherwise.
+#[derive(Clone, Debug)]
+pub struct MetricsLayer {
+    methods: Arc<HashSet<String>>,
+}
+
+impl MetricsLayer {
+    /// Labels the methods of the services named in `services`, e.g.
+    /// `ibc.core.client.v1.Query`.
+    pub fn new<'a>(services: impl IntoIterator<Item = &'a str>) -> Self {
+        let services: HashSet<&str> = services.into_iter().collect();
+        let methods = SERVICE_METHODS
+            .iter()
+            .filter(|(service, _)| services.contains(service))
+            .flat_map(|(service, methods)| {
+                methods
+                    .iter()
+                    .map(move |method| format!("/{}/{}", service, method))
+            })
+            .collect();
+        Self {
+            methods: Arc::new(methods),
+        }
+    }
+
+    fn method_label(&self, path: &str) -> String {
+        if self.methods.contains(path) {
+            path.to_owned()
+        } else {
+            UNKNOWN_METHOD.to_owned()
+        }
+    }
+}
+
+impl Default for MetricsLayer {
+    /// Labels the methods of every service the server can mount.
+    fn default() -> Self {
+        Self::new(SERVICE_METHODS.iter().map(|(service, _)| *service))
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsService<S> {
+    inner: S,
+    layer: MetricsLayer,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for MetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let method = self.layer.method_label(req.uri().path());
+        let start = Instant::now();
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let res = future.await;
+            // Unary handlers that fail answer with a trailers-only response,
+            // so the status code of a failure is found in the headers.
+            let code = match &res {
+                Ok(res) => res
+                    .headers()
+                    .get("grpc-status")
+                    .map(|code| Code::from_bytes(code.as_bytes()))
+                    .unwrap_or(Code::Ok),
+                Err(_) => Code::Internal,
+            };
+            METRICS.observe_request(&method, start.elapsed(), code);
+            res
+        })
+    }
+}
+
+/// Serves the metrics in the Prometheus text format on `GET /metrics` until
+/// the returned task is aborted.
+pub(crate) fn spawn_metrics_server(addr: SocketAddr) -> crate::Result<tokio::task::JoinHandle<()>> {
+    let builder = hyper::Server::try_bind(&addr).map_err(|e| {
+        ServerError::Config(format!("failed to bind metrics endpoint {}: {}", addr, e))
+    })?;
+
+    let make_service = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(service_fn(|req: Request<Body>| async move {
+            let res = if req.uri().path() == "/metrics" {
+                Response::builder()
+                    .header(http::header::CONTENT_TYPE, TextEncoder::new().format_type())
+                    .body(Body::from(METRICS.encode()))
+            } else {
+                Response::builder()
+                    .status(http::StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+            };
+            Ok::<_, Infallible>(res.expect("valid response"))
+        }))
+    });
+
+    log::info!("metrics listening on {}", addr);
+    Ok(tokio::spawn(async move {
+        if let Err(e) = builder.serve(make_service).await {
+            log::error!("metrics endpoint failed: {}", e);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_metrics() {
+        METRICS.observe_request(
+            "/ibc.core.client.v1.Query/ClientStates",
+            Duration::ZERO,
+            Code::NotFound,
+        );
+        METRICS.observe_paths_scanned("clients", 3);
+
+        let text = String::from_utf8(METRICS.encode()).unwrap();
+        assert!(text.contains(
+            "ibc_grpc_request_errors_total{code=\"NotFound\",method=\"/ibc.core.client.v1.Query/ClientStates\"}"
+        ));
+        assert!(text.contains("ibc_store_paths_scanned_total{prefix=\"clients\"}"));
+    }
+
+    #[test]
+    fn test_method_label() {
+        let layer = MetricsLayer::new(["ibc.core.client.v1.Query", "grpc.health.v1.Health"]);
+        assert_eq!(
+            layer.method_label("/ibc.core.client.v1.Query/ClientState"),
+            "/ibc.core.client.v1.Query/ClientState"
+        );
+        assert_eq!(
+            layer.method_label("/grpc.health.v1.Health/Check"),
+            "/grpc.health.v1.Health/Check"
+        );
+        // Not mounted.
+        assert_eq!(
+            layer.method_label("/ibc.core.channel.v1.Query/Channel"),
+            UNKNOWN_METHOD
+        );
+        assert_eq!(
+            layer.method_label("/ibc.core.client.v1.Query/Made/Up"),
+            UNKNOWN_METHOD
+        );
+        assert_eq!(layer.method_label("/random-1234"), UNKNOWN_METHOD);
+
+        assert_eq!(
+            MetricsLayer::default().method_label("/cosmos.auth.v1beta1.Query/Account"),
+            "/cosmos.auth.v1beta1.Query/Account"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{self, Either, Ready};
+use http::{Request, Response};
+use tonic::body::BoxBody;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tonic::Status;
+use tower::{Layer, Service};
+
+use crate::auth::sha256_hex;
+
+/// The identity of a client authenticated by its TLS certificate. It is put
+/// into the request extensions, where handlers can read it with
+/// [`peer_identity`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// The subject distinguished name, e.g. `CN=relayer, O=Example`.
+    pub subject: String,
+    /// The lowercase hex SHA-256 of the DER encoded SubjectPublicKeyInfo.
+    pub spki_sha256: String,
+}
+
+impl PeerIdentity {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        Some(PeerIdentity {
+            subject: cert.subject().to_string(),
+            spki_sha256: sha256_hex(cert.public_key().raw),
+        })
+    }
+}
+
+impl std::fmt::Display for PeerIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.subject)
+    }
+}
+
+/// Returns the authenticated client of a request served over mutual TLS.
+pub fn peer_identity<T>(request: &tonic::Request<T>) -> Option<&PeerIdentity> {
+    request.extensions().get::<PeerIdentity>()
+}
+
+/// The address of the client of `req`, as `tonic::Request::remote_addr`
+/// reads it.
+fn remote_addr<B>(req: &Request<B>) -> Option<SocketAddr> {
+    let extensions = req.extensions();
+    extensions
+        .get::<TcpConnectInfo>()
+        .and_then(TcpConnectInfo::remote_addr)
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        })
+}
+
+/// The client certificates allowed to query the server, matched by subject
+/// or by SPKI hash.
+#[derive(Clone, Debug, Default)]
+pub struct ClientCertAllowList {
+    subjects: HashSet<String>,
+    spki_sha256: HashSet<String>,
+}
+
+impl ClientCertAllowList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subjects.insert(subject.into());
+        self
+    }
+
+    /// Allows the certificate whose SubjectPublicKeyInfo has the given hex
+    /// encoded SHA-256 hash.
+    pub fn allow_spki_sha256(mut self, hash: impl AsRef<str>) -> Self {
+        self.spki_sha256.insert(hash.as_ref().to_lowercase());
+        self
+    }
+
+    pub fn is_allowed(&self, peer: &PeerIdentity) -> bool {
+        self.subjects.contains(&peer.subject) || self.spki_sha256.contains(&peer.spki_sha256)
+    }
+}
+
+/// A layer resolving the [`PeerIdentity`] of mutual TLS clients and, given an
+/// allow-list, rejecting the clients not on it. It logs the client of every
+/// request it lets through at debug level.
+#[derive(Clone, Debug, Default)]
+pub struct PeerIdentityLayer {
+    allow_list: Option<Arc<ClientCertAllowList>>,
+}
+
+impl PeerIdentityLayer {
+    pub fn new(allow_list: Option<Arc<ClientCertAllowList>>) -> Self {
+        Self { allow_list }
+    }
+}
+
+impl<S> Layer<S> for PeerIdentityLayer {
+    type Service = PeerIdentityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PeerIdentityService {
+            inner,
+            allow_list: self.allow_list.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PeerIdentityService<S> {
+    inner: S,
+    allow_list: Option<Arc<ClientCertAllowList>>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for PeerIdentityService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let peer = req
+            .extensions()
+            .get::<TlsConnectInfo<TcpConnectInfo>>()
+            .and_then(|info| info.peer_certs())
+            .and_then(|certs| {
+                certs
+                    .first()
+                    .and_then(|cert| PeerIdentity::from_der(cert.get_ref()))
+            });
+
+        let rejection = match (&self.allow_list, &peer) {
+            (Some(_), None) => Some(Status::unauthenticated("client certificate required")),
+            (Some(allow_list), Some(peer)) if !allow_list.is_allowed(peer) => {
+                log::warn!(
+                    "reject client {} with spki sha256 {}",
+                    peer.subject,
+                    peer.spki_sha256
+                );
+                Some(Status::permission_denied("client certificate not allowed"))
+            }
+            _ => None,
+        };
+        if let Some(status) = rejection {
+            return Either::Right(future::ready(Ok(status.to_http())));
+        }
+
+        let remote_addr = remote_addr(&req)
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|| "unknown address".to_owned());
+        match &peer {
+            Some(peer) => log::debug!("{} from {} ({})", req.uri().path(), remote_addr, peer),
+            None => log::debug!("{} from {}", req.uri().path(), remote_addr),
+        }
+
+        if let Some(peer) = peer {
+            req.extensions_mut().insert(peer);
+        }
+        Either::Left(self.inner.call(req))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allow_list() {
+        let peer = PeerIdentity {
+            subject: "CN=relayer".to_owned(),
+            spki_sha256: "ab".repeat(32),
+        };
+
+        assert!(!ClientCertAllowList::new().is_allowed(&peer));
+        assert!(ClientCertAllowList::new()
+            .allow_subject("CN=relayer")
+            .is_allowed(&peer));
+        assert!(ClientCertAllowList::new()
+            .allow_spki_sha256("AB".repeat(32))
+            .is_allowed(&peer));
+        assert!(!ClientCertAllowList::new()
+            .allow_subject("CN=other")
+            .allow_spki_sha256("cd".repeat(32))
+            .is_allowed(&peer));
+    }
+
+    #[test]
+    fn test_peer_identity_from_invalid_der() {
+        assert!(PeerIdentity::from_der(b"not a certificate").is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use ibc_proto::cosmos::base::query::v1beta1::{PageRequest, PageResponse};
+
+use crate::ServerError;
+
+/// The page size when a request sets no limit, as in the Cosmos SDK.
+const DEFAULT_PAGE_LIMIT: u64 = 100;
+
+/// Cuts the page `pagination` asks for out of `items`, the way the Cosmos SDK
+/// pages through a store prefix. The `next_key` of a page is the big-endian
+/// offset of the next one.
+pub(crate) fn paginate<T>(
+    mut items: Vec<T>,
+    pagination: Option<PageRequest>,
+) -> Result<(Vec<T>, PageResponse), ServerError> {
+    let page = pagination.unwrap_or_default();
+    let offset = if page.key.is_empty() {
+        page.offset
+    } else if page.offset != 0 {
+        return Err(ServerError::InvalidArgument(
+            "either offset or key is expected, got both".to_owned(),
+        ));
+    } else {
+        let key = <[u8; 8]>::try_from(page.key.as_slice()).map_err(|_| {
+            ServerError::InvalidArgument(format!("pagination key {}", hex::encode(&page.key)))
+        })?;
+        u64::from_be_bytes(key)
+    };
+    let limit = match page.limit {
+        0 => DEFAULT_PAGE_LIMIT,
+        limit => limit,
+    };
+
+    let total = items.len() as u64;
+    let end = offset.saturating_add(limit).min(total);
+    let next_key = if end < total {
+        end.to_be_bytes().to_vec()
+    } else {
+        vec![]
+    };
+    if page.reverse {
+        items.reverse();
+    }
+    let items = items
+        .into_iter()
+        .skip(offset.min(total) as usize)
+        .take(end.saturating_sub(offset) as usize)
+        .collect();
+    Ok((
+        items,
+        PageResponse {
+            next_key,
+            total: if page.count_total { total } else { 0 },
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<u64> = (0..5).collect();
+
+        let (page, response) = paginate(
+            items.clone(),
+            Some(PageRequest {
+                limit: 2,
+                count_total: true,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(page, [0, 1]);
+        assert_eq!(response.total, 5);
+
+        let (page, response) = paginate(
+            items.clone(),
+            Some(PageRequest {
+                key: response.next_key,
+                limit: 2,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(page, [2, 3]);
+        assert_eq!(response.total, 0);
+
+        let (page, response) = paginate(
+            items.clone(),
+            Some(PageRequest {
+                key: response.next_key,
+                limit: 2,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(page, [4]);
+        assert!(response.next_key.is_empty());
+
+        let (page, _) = paginate(
+            items.clone(),
+            Some(PageRequest {
+                offset: 1,
+                limit: 2,
+                reverse: true,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(page, [3, 2]);
+
+        let (page, response) = paginate(items.clone(), None).unwrap();
+        assert_eq!(page, items);
+        assert!(response.next_key.is_empty());
+
+        let (page, _) = paginate(
+            items.clone(),
+            Some(PageRequest {
+                offset: 9,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert!(page.is_empty());
+
+        assert!(paginate(
+            items.clone(),
+            Some(PageRequest {
+                key: vec![1],
+                ..Default::default()
+            })
+        )
+        .is_err());
+        assert!(paginate(
+            items,
+            Some(PageRequest {
+                key: vec![0; 8],
+                offset: 1,
+                ..Default::default()
+            })
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either, Ready};
+use http::{Request, Response};
+use tonic::body::BoxBody;
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo};
+use tower::{Layer, Service};
+
+use crate::auth::ApiKeyLabel;
+use crate::error::ServerError;
+use crate::health::is_health_check;
+
+/// The methods scanning a whole store prefix rather than reading one key.
+const SCAN_METHODS: &[&str] = &[
+    "ClientStates",
+    "ConsensusStates",
+    "Connections",
+    "Channels",
+    "ConnectionChannels",
+    "PacketCommitments",
+    "PacketAcknowledgements",
+    "UnreceivedPackets",
+    "UnreceivedAcks",
+    "DenomTraces",
+    "IncentivizedPackets",
+    "IncentivizedPacketsForChannel",
+    "FeeEnabledChannels",
+];
+
+/// How often the buckets of idle clients are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket quota applied per client, i.e. per API key when the request
+/// carries one and per peer IP address otherwise. Point lookups cost one
+/// token, prefix scans cost `scan_cost` tokens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub(crate) capacity: u32,
+    pub(crate) refill_per_second: f64,
+    pub(crate) scan_cost: u32,
+}
+
+impl RateLimit {
+    /// Allows bursts of `capacity` tokens, refilled at `refill_per_second`.
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        Self {
+            capacity,
+            refill_per_second,
+            scan_cost: 10,
+        }
+    }
+
+    /// The cost of the prefix-scanning queries, 10 tokens by default.
+    pub fn scan_cost(mut self, cost: u32) -> Self {
+        self.scan_cost = cost;
+        self
+    }
+
+    fn cost_of(&self, method: &str) -> u32 {
+        let name = method.rsplit('/').next().unwrap_or(method);
+        if SCAN_METHODS.contains(&name) {
+            self.scan_cost
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Charges `client` for a call of the gRPC `method`, or returns how long
+    /// the client has to wait until it can afford it.
+    pub(crate) fn try_acquire(&self, client: &str, method: &str) -> Result<(), Duration> {
+        self.try_acquire_at(client, self.limit.cost_of(method), Instant::now())
+    }
+
+    /// Takes `cost` tokens from the bucket of `client`, or returns how long
+    /// the client has to wait until enough tokens are available.
+    fn try_acquire_at(&self, client: &str, cost: u32, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.limit.capacity);
+        let refill = self.limit.refill_per_second;
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let bucket = buckets.entry(client.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill).min(capacity);
+        bucket.updated = now;
+
+        let cost = f64::from(cost);
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((cost - bucket.tokens) / refill))
+        }
+    }
+
+    /// Drops the buckets of the clients not seen for as long as it takes to
+    /// fill a bucket: they are full again, so a new bucket is the same.
+    fn prune_at(&self, now: Instant) {
+        let idle = f64::from(self.limit.capacity) / self.limit.refill_per_second;
+        let idle = Duration::try_from_secs_f64(idle).unwrap_or(Duration::MAX);
+        self.buckets
+            .lock()
+            .expect("rate limiter lock poisoned")
+            .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < idle);
+    }
+}
+
+/// Prunes the buckets of `limiter` every `PRUNE_INTERVAL` until the returned
+/// task is aborted.
+pub(crate) fn spawn_pruning(limiter: Arc<RateLimiter>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            limiter.prune_at(Instant::now());
+        }
+    })
+}
+
+/// A layer rejecting the requests of clients over their [`RateLimit`] with
+/// `RESOURCE_EXHAUSTED` and a `google.rpc.RetryInfo` detail. `None` disables
+/// rate limiting.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitLayer {
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimitLayer {
+    pub(crate) fn new(limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let limiter = match &self.limiter {
+            Some(limiter) if !is_health_check(&req) => limiter,
+            _ => return Either::Left(self.inner.call(req)),
+        };
+
+        let method = req.uri().path();
+        let client = client_key(&req);
+        if let Err(retry_after) = limiter.try_acquire(&client, method) {
+            log::warn!("rate limit client {} calling {}", client, method);
+            let status = ServerError::RateLimited(retry_after)
+                .into_status_with([("method", method.to_owned())]);
+            return Either::Right(future::ready(Ok(status.to_http())));
+        }
+
+        Either::Left(self.inner.call(req))
+    }
+}
+
+/// The API key label of the request if any, the peer IP address otherwise.
+fn client_key<B>(req: &Request<B>) -> String {
+    let extensions = req.extensions();
+    let remote_addr = extensions
+        .get::<TcpConnectInfo>()
+        .and_then(|info| info.remote_addr())
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<TcpConnectInfo>>()
+                .and_then(|info| info.get_ref().remote_addr())
+        });
+    rate_limit_key(extensions.get::<ApiKeyLabel>(), remote_addr)
+}
+
+/// The key of the bucket of a client, its API key if any, its IP address
+/// otherwise.
+pub(crate) fn rate_limit_key(
+    label: Option<&ApiKeyLabel>,
+    remote_addr: Option<SocketAddr>,
+) -> String {
+    match (label, remote_addr) {
+        (Some(label), _) => format!("key:{}", label),
+        (None, Some(addr)) => format!("ip:{}", addr.ip()),
+        (None, None) => "unknown".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_methods_cost_more() {
+        let limit = RateLimit::new(100, 10.0).scan_cost(25);
+        assert_eq!(limit.cost_of("/ibc.core.channel.v1.Query/Channels"), 25);
+        assert_eq!(limit.cost_of("/ibc.core.channel.v1.Query/Channel"), 1);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(RateLimit::new(10, 2.0).scan_cost(10));
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at("a", 10, start).is_ok());
+        let retry_after = limiter.try_acquire_at("a", 1, start).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(500));
+
+        // Clients have their own buckets.
+        assert!(limiter.try_acquire_at("b", 10, start).is_ok());
+
+        let later = start + Duration::from_secs(1);
+        assert!(limiter.try_acquire_at("a", 2, later).is_ok());
+        assert!(limiter.try_acquire_at("a", 1, later).is_err());
+    }
+
+    #[test]
+    fn test_prune_idle_buckets() {
+        // A bucket is full again 5 seconds after its last call.
+        let limiter = RateLimiter::new(RateLimit::new(10, 2.0));
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at("idle", 10, start).is_ok());
+        let later = start + Duration::from_secs(4);
+        assert!(limiter.try_acquire_at("active", 1, later).is_ok());
+
+        limiter.prune_at(start + Duration::from_secs(5));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.contains_key("idle"));
+        assert!(buckets.contains_key("active"));
+    }
+}
@@ -1,12 +1,24 @@
+use std::fmt::Display;
+use std::future::{self, Future};
+use std::str::FromStr;
 use std::sync::Arc;
-use std::{net::SocketAddr, str::FromStr};
 
 use ibc::core::ics03_connection::connection::{ConnectionEnd, IdentifiedConnectionEnd};
 use ibc::core::ics04_channel::channel::{ChannelEnd, IdentifiedChannelEnd};
 use ibc::core::ics04_channel::packet::Sequence;
-use ibc::core::ics24_host::identifier::{ChannelId, ConnectionId, PortId};
+use ibc::core::ics24_host::error::ValidationError;
+use ibc::core::ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId};
 use ibc::core::ics24_host::{path, Path as IbcPath};
 
+use ibc_proto::cosmos::auth::v1beta1::query_server::QueryServer as AuthQueryServer;
+use ibc_proto::cosmos::base::tendermint::v1beta1::service_server::ServiceServer as TendermintQueryServer;
+use ibc_proto::cosmos::staking::v1beta1::query_server::QueryServer as StakingQueryServer;
+use ibc_proto::ibc::applications::fee::v1::query_server::QueryServer as FeeQueryServer;
+use ibc_proto::ibc::applications::interchain_accounts::{
+    controller::v1::query_server::QueryServer as IcaControllerQueryServer,
+    host::v1::query_server::QueryServer as IcaHostQueryServer,
+};
+use ibc_proto::ibc::applications::transfer::v1::query_server::QueryServer as TransferQueryServer;
 use ibc_proto::ibc::core::{
     channel::v1::{
         query_server::{Query as ChannelQuery, QueryServer as ChannelQueryServer},
@@ -43,69 +55,492 @@ use ibc_proto::ibc::core::{
     },
 };
 
-use tonic::{transport::Server, Request, Response, Status};
-
-use crate::{IbcStore, Path, StoreHeight};
+use tonic::transport::{NamedService, Server};
+use tonic::{codec::CompressionEncoding, Request, Response, Status};
+use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
+
+use crate::abci::spawn_abci_server;
+use crate::auth::ApiKeyLayer;
+use crate::builder::ServerOptions;
+use crate::cosmos::{IbcAuthService, IbcStakingService};
+use crate::fee::IbcFeeService;
+use crate::gateway::{spawn_rest_gateway, RestGateway};
+use crate::health;
+use crate::ica::{IbcIcaControllerService, IbcIcaHostService};
+use crate::instrument::InstrumentedStore;
+use crate::limit::RequestSizeLimitLayer;
+use crate::metrics::{spawn_metrics_server, MetricsLayer};
+use crate::mtls::PeerIdentityLayer;
+use crate::rate_limit::{spawn_pruning, RateLimitLayer};
+use crate::telemetry::{record_span_field, TraceLayer};
+use crate::tendermint::IbcTendermintService;
+use crate::transfer::IbcTransferService;
+use crate::types::ConsistencyPolicy;
+use crate::web::MaybeGrpcWebLayer;
+use crate::{AsyncIbcStore, CatchPanicLayer, Path, ServerError, StoreHeight};
 
 pub const CHAIN_REVISION_NUMBER: u64 = 0;
 
-pub struct IbcGrpcService<Store: IbcStore> {
+/// Maps a malformed request field to a status whose error details name the
+/// field.
+pub(crate) fn invalid_field<E: Into<ServerError>>(field: &'static str) -> impl FnOnce(E) -> Status {
+    move |e| e.into().into_status_with([("field", field.to_owned())])
+}
+
+/// Parses the port and channel identifiers of a channel-scoped request and
+/// records them on the request span.
+#[allow(clippy::result_large_err)]
+pub(crate) fn parse_channel(
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(PortId, ChannelId), Status> {
+    let port_id = PortId::from_str(port_id).map_err(invalid_field("port_id"))?;
+    let channel_id = ChannelId::from_str(channel_id).map_err(invalid_field("channel_id"))?;
+    record_span_field("port_id", &port_id);
+    record_span_field("channel_id", &channel_id);
+    Ok((port_id, channel_id))
+}
+
+/// Parses a packet sequence. Sequences start at 1, as in ibc-go.
+#[allow(clippy::result_large_err)]
+pub(crate) fn parse_sequence(sequence: u64) -> Result<Sequence, Status> {
+    if sequence == 0 {
+        return Err(
+            ServerError::InvalidArgument("packet sequence cannot be 0".to_owned())
+                .into_status_with([("field", "sequence".to_owned())]),
+        );
+    }
+    Ok(Sequence::from(sequence))
+}
+
+/// Maps a failed store read to a status whose error details name the path
+/// being read.
+pub(crate) fn store_error<P: Display + ?Sized>(
+    path: &P,
+) -> impl FnOnce(ServerError) -> Status + '_ {
+    move |e| e.into_status_with([("path", path.to_string())])
+}
+
+/// Makes a generated query server accept and send gzip compressed messages
+/// if `gzip` is set.
+macro_rules! maybe_gzip {
+    ($gzip:expr, $service:expr) => {{
+        let service = $service;
+        if $gzip {
+            service
+                .accept_compressed(CompressionEncoding::Gzip)
+                .send_compressed(CompressionEncoding::Gzip)
+        } else {
+            service
+        }
+    }};
+}
+
+/// The encoded `FileDescriptorSet` of a service's queries, compiled from the
+/// vendored protos by the build script.
+macro_rules! file_descriptor_set {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, "_descriptor.bin"))
+    };
+}
+
+/// Builds the gRPC reflection service from the reflection descriptors, the
+/// descriptors of the mounted services and the encoded `FileDescriptorSet`s
+/// of the other mounted services.
+fn reflection_service(
+    service_descriptor_sets: &[&'static [u8]],
+    file_descriptor_sets: &[Vec<u8>],
+) -> crate::Result<ServerReflectionServer<impl ServerReflection>> {
+    let builder = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(tonic_reflection::proto::FILE_DESCRIPTOR_SET);
+    service_descriptor_sets
+        .iter()
+        .copied()
+        .chain(file_descriptor_sets.iter().map(Vec::as_slice))
+        .fold(builder, |builder, set| {
+            builder.register_encoded_file_descriptor_set(set)
+        })
+        .build()
+        .map_err(|e| ServerError::Config(format!("invalid file descriptor set: {}", e)))
+}
+
+/// Handles a list query entry that is inconsistent with the store, such as a
+/// key without a value. Strict mode fails the request, lenient mode logs the
+/// entry so that the caller can skip it.
+#[allow(clippy::result_large_err)]
+fn on_inconsistency(
+    policy: ConsistencyPolicy,
+    path: &dyn Display,
+    reason: &str,
+) -> Result<(), Status> {
+    match policy {
+        ConsistencyPolicy::Strict => {
+            Err(ServerError::Inconsistent(format!("{}: {}", path, reason))
+                .into_status_with([("path", path.to_string())]))
+        }
+        ConsistencyPolicy::Lenient => {
+            log::warn!("skip inconsistent store entry {}: {}", path, reason);
+            Ok(())
+        }
+    }
+}
+
+/// Checks that a batched getter returned one entry per path, as `IbcStore`
+/// requires. Lenient mode counts missing entries as absent and drops the
+/// extra ones.
+#[allow(clippy::result_large_err)]
+fn check_batch<T>(
+    policy: ConsistencyPolicy,
+    path: &dyn Display,
+    mut entries: Vec<Option<T>>,
+    len: usize,
+) -> Result<Vec<Option<T>>, Status> {
+    if entries.len() != len {
+        on_inconsistency(
+            policy,
+            path,
+            &format!("store returned {} entries for {} paths", entries.len(), len),
+        )?;
+        entries.resize_with(len, || None);
+    }
+    Ok(entries)
+}
+
+/// The background tasks of a server, such as the metrics endpoint, aborted
+/// when dropped.
+#[derive(Default)]
+struct BackgroundTasks(Vec<tokio::task::JoinHandle<()>>);
+
+impl BackgroundTasks {
+    fn push(&mut self, task: tokio::task::JoinHandle<()>) {
+        self.0.push(task);
+    }
+}
+
+impl Drop for BackgroundTasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+pub struct IbcGrpcService<Store: AsyncIbcStore> {
     store: Arc<Store>,
-    addr: SocketAddr,
+    options: ServerOptions,
 }
 
 impl<Store> IbcGrpcService<Store>
 where
-    Store: IbcStore + 'static,
+    Store: AsyncIbcStore + 'static,
 {
-    pub fn new(store: Store, addr: String) -> Self {
+    pub(crate) fn with_options(store: Store, options: ServerOptions) -> Self {
         IbcGrpcService {
             store: Arc::new(store),
-            addr: addr.parse().unwrap(),
+            options,
         }
     }
 
-    pub async fn run(self) {
+    pub async fn run(self) -> crate::Result<()> {
+        self.run_with_shutdown(future::pending()).await
+    }
+
+    /// Serves until `signal` completes, then stops accepting connections and
+    /// returns once the in-flight requests are answered.
+    pub async fn run_with_shutdown<F>(self, signal: F) -> crate::Result<()>
+    where
+        F: Future<Output = ()>,
+    {
         log::info!("ibc run");
 
-        let ibc_client_service = self.client_service();
-        let ibc_conn_service = self.connection_service();
-        let ibc_channel_service = self.channel_service();
+        let options = &self.options;
+        let mut server = Server::builder()
+            .http2_keepalive_interval(options.http2_keepalive_interval)
+            .http2_keepalive_timeout(options.http2_keepalive_timeout)
+            .max_concurrent_streams(options.max_concurrent_streams);
+        if let Some(limit) = options.concurrency_limit_per_connection {
+            server = server.concurrency_limit_per_connection(limit);
+        }
+        if let Some(timeout) = options.timeout {
+            server = server.timeout(timeout);
+        }
+        if let Some(tls) = options.tls.clone() {
+            server = server.tls_config(tls).map_err(ServerError::Transport)?;
+        }
+        // gRPC-Web requests of browsers are mostly sent over HTTP/1.1.
+        if options.grpc_web.is_some() {
+            server = server.accept_http1(true);
+        }
+
+        let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+        let (services, descriptor_sets): (Vec<&'static str>, Vec<&'static [u8]>) = [
+            (
+                options.client_service,
+                ClientQueryServer::<IbcClientService<Store>>::NAME,
+                file_descriptor_set!("client").as_slice(),
+            ),
+            (
+                options.connection_service,
+                ConnectionQueryServer::<IbcConnectionService<Store>>::NAME,
+                file_descriptor_set!("connection").as_slice(),
+            ),
+            (
+                options.channel_service,
+                ChannelQueryServer::<IbcChannelService<Store>>::NAME,
+                file_descriptor_set!("channel").as_slice(),
+            ),
+            (
+                options.transfer_store.is_some(),
+                TransferQueryServer::<IbcTransferService>::NAME,
+                file_descriptor_set!("transfer").as_slice(),
+            ),
+            (
+                options.ica_store.is_some(),
+                IcaControllerQueryServer::<IbcIcaControllerService>::NAME,
+                file_descriptor_set!("ica_controller").as_slice(),
+            ),
+            (
+                options.ica_store.is_some(),
+                IcaHostQueryServer::<IbcIcaHostService>::NAME,
+                file_descriptor_set!("ica_host").as_slice(),
+            ),
+            (
+                options.fee_store.is_some(),
+                FeeQueryServer::<IbcFeeService>::NAME,
+                file_descriptor_set!("fee").as_slice(),
+            ),
+            (
+                options.node_info.is_some(),
+                TendermintQueryServer::<IbcTendermintService<Store>>::NAME,
+                file_descriptor_set!("tendermint").as_slice(),
+            ),
+            (
+                options.staking_host.is_some(),
+                StakingQueryServer::<IbcStakingService>::NAME,
+                file_descriptor_set!("staking").as_slice(),
+            ),
+            (
+                options.auth_host.is_some(),
+                AuthQueryServer::<IbcAuthService>::NAME,
+                file_descriptor_set!("auth").as_slice(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(enabled, _, _)| *enabled)
+        .map(|(_, name, descriptor_set)| (name, descriptor_set))
+        .unzip();
+        let reflection_service = options
+            .reflection
+            .then(|| reflection_service(&descriptor_sets, &options.file_descriptor_sets))
+            .transpose()?;
+        let metrics_layer = MetricsLayer::new(
+            services
+                .iter()
+                .copied()
+                .chain(Some("grpc.health.v1.Health"))
+                .chain(
+                    options
+                        .reflection
+                        .then_some("grpc.reflection.v1alpha.ServerReflection"),
+                ),
+        );
+
+        // Dropping the tasks aborts them, so none outlives a failed start.
+        let mut tasks = BackgroundTasks::default();
+        if let Some(addr) = options.metrics_addr {
+            tasks.push(spawn_metrics_server(addr)?);
+        }
+        if let Some(addr) = options.rest_addr {
+            tasks.push(spawn_rest_gateway(addr, self.rest_gateway())?);
+        }
+        if let Some(addr) = options.abci_addr {
+            tasks.push(spawn_abci_server(addr, Arc::clone(&self.store))?);
+        }
+        if let Some(limiter) = options.rate_limiter.clone() {
+            tasks.push(spawn_pruning(limiter));
+        }
+        match options.health_check {
+            Some(check) => tasks.push(tokio::spawn(health::monitor(
+                Arc::clone(&self.store),
+                check,
+                health_reporter,
+                services,
+            ))),
+            None => health::set_serving(&mut health_reporter, &services).await,
+        }
 
-        Server::builder()
-            .add_service(ibc_client_service)
-            .add_service(ibc_conn_service)
-            .add_service(ibc_channel_service)
-            .serve(self.addr)
+        let served = server
+            .layer(MaybeGrpcWebLayer::new(options.grpc_web.clone()))
+            .layer(TraceLayer)
+            .layer(metrics_layer)
+            .layer(CatchPanicLayer)
+            .layer(PeerIdentityLayer::new(
+                options.client_cert_allow_list.clone(),
+            ))
+            .layer(ApiKeyLayer::new(options.api_keys.clone()))
+            .layer(RateLimitLayer::new(options.rate_limiter.clone()))
+            .layer(RequestSizeLimitLayer::new(options.max_request_size))
+            .add_service(health_service)
+            .add_optional_service(reflection_service)
+            .add_optional_service(options.client_service.then(|| self.client_service()))
+            .add_optional_service(
+                options
+                    .connection_service
+                    .then(|| self.connection_service()),
+            )
+            .add_optional_service(options.channel_service.then(|| self.channel_service()))
+            .add_optional_service(self.transfer_service())
+            .add_optional_service(self.ica_controller_service())
+            .add_optional_service(self.ica_host_service())
+            .add_optional_service(self.fee_service())
+            .add_optional_service(self.tendermint_service())
+            .add_optional_service(self.staking_service())
+            .add_optional_service(self.auth_service())
+            .serve_with_shutdown(options.addr, signal)
             .await
-            .unwrap();
+            .map_err(ServerError::Transport);
+
+        drop(tasks);
+        served?;
+
+        log::info!("ibc stopped");
+        Ok(())
+    }
+
+    fn rest_gateway(&self) -> RestGateway<Store> {
+        let options = &self.options;
+        RestGateway {
+            client: options
+                .client_service
+                .then(|| IbcClientService::new(Arc::clone(&self.store), options.policy)),
+            connection: options
+                .connection_service
+                .then(|| IbcConnectionService::new(Arc::clone(&self.store), options.policy)),
+            channel: options
+                .channel_service
+                .then(|| IbcChannelService::new(Arc::clone(&self.store), options.policy)),
+            api_keys: options.api_keys.clone(),
+            rate_limiter: options.rate_limiter.clone(),
+        }
     }
 
     pub fn client_service(&self) -> ClientQueryServer<IbcClientService<Store>> {
-        ClientQueryServer::new(IbcClientService::new(Arc::clone(&self.store)))
+        maybe_gzip!(
+            self.options.gzip,
+            ClientQueryServer::new(IbcClientService::new(
+                Arc::clone(&self.store),
+                self.options.policy,
+            ))
+        )
     }
 
     pub fn connection_service(&self) -> ConnectionQueryServer<IbcConnectionService<Store>> {
-        ConnectionQueryServer::new(IbcConnectionService::new(Arc::clone(&self.store)))
+        maybe_gzip!(
+            self.options.gzip,
+            ConnectionQueryServer::new(IbcConnectionService::new(
+                Arc::clone(&self.store),
+                self.options.policy,
+            ))
+        )
     }
 
     pub fn channel_service(&self) -> ChannelQueryServer<IbcChannelService<Store>> {
-        ChannelQueryServer::new(IbcChannelService::new(Arc::clone(&self.store)))
+        maybe_gzip!(
+            self.options.gzip,
+            ChannelQueryServer::new(IbcChannelService::new(
+                Arc::clone(&self.store),
+                self.options.policy,
+            ))
+        )
+    }
+
+    /// The transfer query service, if a transfer store was given to the
+    /// builder.
+    pub fn transfer_service(&self) -> Option<TransferQueryServer<IbcTransferService>> {
+        let store = self.options.transfer_store.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            TransferQueryServer::new(IbcTransferService::new(store))
+        ))
+    }
+
+    /// The ICA controller query service, if an ICA store was given to the
+    /// builder.
+    pub fn ica_controller_service(
+        &self,
+    ) -> Option<IcaControllerQueryServer<IbcIcaControllerService>> {
+        let store = self.options.ica_store.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            IcaControllerQueryServer::new(IbcIcaControllerService::new(store))
+        ))
+    }
+
+    /// The ICA host query service, if an ICA store was given to the builder.
+    pub fn ica_host_service(&self) -> Option<IcaHostQueryServer<IbcIcaHostService>> {
+        let store = self.options.ica_store.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            IcaHostQueryServer::new(IbcIcaHostService::new(store))
+        ))
+    }
+
+    /// The fee query service, if a fee store was given to the builder.
+    pub fn fee_service(&self) -> Option<FeeQueryServer<IbcFeeService>> {
+        let store = self.options.fee_store.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            FeeQueryServer::new(IbcFeeService::new(store))
+        ))
+    }
+
+    /// The tendermint service, if the builder was given a chain id for it.
+    pub fn tendermint_service(&self) -> Option<TendermintQueryServer<IbcTendermintService<Store>>> {
+        let node_info = self.options.node_info.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            TendermintQueryServer::new(IbcTendermintService::new(
+                Arc::clone(&self.store),
+                node_info,
+            ))
+        ))
+    }
+
+    /// The staking service, if the builder was given a staking host.
+    pub fn staking_service(&self) -> Option<StakingQueryServer<IbcStakingService>> {
+        let host = self.options.staking_host.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            StakingQueryServer::new(IbcStakingService::new(host))
+        ))
+    }
+
+    /// The auth service, if the builder was given an auth host.
+    pub fn auth_service(&self) -> Option<AuthQueryServer<IbcAuthService>> {
+        let host = self.options.auth_host.clone()?;
+        Some(maybe_gzip!(
+            self.options.gzip,
+            AuthQueryServer::new(IbcAuthService::new(host))
+        ))
     }
 }
 
-pub struct IbcClientService<Store: IbcStore> {
-    store: Arc<Store>,
+pub struct IbcClientService<Store: AsyncIbcStore> {
+    store: Arc<InstrumentedStore<Store>>,
+    policy: ConsistencyPolicy,
 }
 
-impl<Store: IbcStore> IbcClientService<Store> {
-    pub fn new(store: Arc<Store>) -> Self {
-        Self { store }
+impl<Store: AsyncIbcStore> IbcClientService<Store> {
+    pub fn new(store: Arc<Store>, policy: ConsistencyPolicy) -> Self {
+        Self {
+            store: Arc::new(InstrumentedStore::new(store)),
+            policy,
+        }
     }
 }
 
 #[tonic::async_trait]
-impl<Store: IbcStore + 'static> ClientQuery for IbcClientService<Store> {
+impl<Store: AsyncIbcStore + 'static> ClientQuery for IbcClientService<Store> {
     async fn client_state(
         &self,
         _request: Request<QueryClientStateRequest>,
@@ -115,14 +550,12 @@ impl<Store: IbcStore + 'static> ClientQuery for IbcClientService<Store> {
 
     async fn client_states(
         &self,
-        request: Request<QueryClientStatesRequest>,
+        _request: Request<QueryClientStatesRequest>,
     ) -> Result<Response<QueryClientStatesResponse>, Status> {
-        log::info!("Got client states request: {:?}", request);
-
         let path = "clients"
             .to_owned()
             .try_into()
-            .map_err(|e| Status::invalid_argument(format!("{:?}", e)))?;
+            .expect("'clients' expected to be a valid Path");
 
         let client_state_paths = |path: Path| -> Option<path::ClientStatePath> {
             match path.try_into() {
@@ -134,20 +567,24 @@ impl<Store: IbcStore + 'static> ClientQuery for IbcClientService<Store> {
         let keys = self
             .store
             .get_paths_by_prefix(&path)
-            .map_err(Status::internal)?;
+            .await
+            .map_err(store_error(&path))?;
         let mut client_states = Vec::with_capacity(keys.len());
 
         // Todo: fixme after the light client state defined.
         for path in keys.into_iter().filter_map(client_state_paths) {
-            client_states.push(
-                self.store
-                    .get_client_state(StoreHeight::Latest, &path)
-                    .map(|_client_state| IdentifiedClientState {
-                        client_id: path.0.to_string(),
-                        client_state: None,
-                    })
-                    .map_err(Status::data_loss)?,
-            );
+            let client_state = self
+                .store
+                .get_client_state(StoreHeight::Latest, &path)
+                .await
+                .map_err(store_error(&path))?;
+            match client_state {
+                Some(_client_state) => client_states.push(IdentifiedClientState {
+                    client_id: path.0.to_string(),
+                    client_state: None,
+                }),
+                None => on_inconsistency(self.policy, &path, "no client state at path")?,
+            }
         }
 
         Ok(Response::new(QueryClientStatesResponse {
@@ -167,34 +604,40 @@ impl<Store: IbcStore + 'static> ClientQuery for IbcClientService<Store> {
         &self,
         request: Request<QueryConsensusStatesRequest>,
     ) -> Result<Response<QueryConsensusStatesResponse>, Status> {
-        log::info!("Got consensus states request: {:?}", request);
-
         let path = format!("clients/{}/consensusStates", request.get_ref().client_id)
             .try_into()
-            .map_err(|e| Status::invalid_argument(format!("{:?}", e)))?;
+            .map_err(invalid_field::<ServerError>("client_id"))?;
+        record_span_field("client_id", &request.get_ref().client_id);
 
         let keys = self
             .store
             .get_paths_by_prefix(&path)
-            .map_err(Status::internal)?;
+            .await
+            .map_err(store_error(&path))?;
         let mut consensus_states = Vec::with_capacity(keys.len());
 
         // Todo: fixme after light client consensus state defined.
         for path in keys.into_iter() {
-            if let Ok(IbcPath::ClientConsensusState(path)) = path.try_into() {
-                let _consensus_state = self
-                    .store
-                    .get_consensus_state(StoreHeight::Latest, &path)
-                    .map_err(Status::data_loss)?;
-                consensus_states.push(ConsensusStateWithHeight {
-                    height: Some(Height {
-                        revision_number: path.epoch,
-                        revision_height: path.height,
-                    }),
-                    consensus_state: None,
-                });
-            } else {
-                panic!("unexpected path")
+            match path.clone().try_into() {
+                Ok(IbcPath::ClientConsensusState(path)) => {
+                    let consensus_state = self
+                        .store
+                        .get_consensus_state(StoreHeight::Latest, &path)
+                        .await
+                        .map_err(store_error(&path))?;
+                    if consensus_state.is_none() {
+                        on_inconsistency(self.policy, &path, "no consensus state at path")?;
+                        continue;
+                    }
+                    consensus_states.push(ConsensusStateWithHeight {
+                        height: Some(Height {
+                            revision_number: path.epoch,
+                            revision_height: path.height,
+                        }),
+                        consensus_state: None,
+                    });
+                }
+                _ => on_inconsistency(self.policy, &path, "unexpected path")?,
             }
         }
 
@@ -240,32 +683,38 @@ impl<Store: IbcStore + 'static> ClientQuery for IbcClientService<Store> {
     }
 }
 
-pub struct IbcConnectionService<Store: IbcStore> {
-    connection_end_adapter: Arc<Store>,
-    connection_ids_adapter: Arc<Store>,
+pub struct IbcConnectionService<Store: AsyncIbcStore> {
+    connection_end_adapter: Arc<InstrumentedStore<Store>>,
+    connection_ids_adapter: Arc<InstrumentedStore<Store>>,
+    policy: ConsistencyPolicy,
 }
 
-impl<Store: IbcStore> IbcConnectionService<Store> {
-    pub fn new(store: Arc<Store>) -> Self {
+impl<Store: AsyncIbcStore> IbcConnectionService<Store> {
+    pub fn new(store: Arc<Store>, policy: ConsistencyPolicy) -> Self {
+        let store = Arc::new(InstrumentedStore::new(store));
         Self {
             connection_end_adapter: Arc::clone(&store),
             connection_ids_adapter: Arc::clone(&store),
+            policy,
         }
     }
 }
 
 #[tonic::async_trait]
-impl<Store: IbcStore + 'static> ConnectionQuery for IbcConnectionService<Store> {
+impl<Store: AsyncIbcStore + 'static> ConnectionQuery for IbcConnectionService<Store> {
     async fn connection(
         &self,
         request: Request<QueryConnectionRequest>,
     ) -> Result<Response<QueryConnectionResponse>, Status> {
         let conn_id = ConnectionId::from_str(&request.get_ref().connection_id)
-            .map_err(|_| Status::invalid_argument("invalid connection id"))?;
+            .map_err(invalid_field("connection_id"))?;
+        record_span_field("connection_id", &conn_id);
+        let path = path::ConnectionsPath(conn_id);
         let conn: Option<ConnectionEnd> = self
             .connection_end_adapter
-            .get_connection_end(StoreHeight::Latest, &path::ConnectionsPath(conn_id))
-            .map_err(Status::data_loss)?;
+            .get_connection_end(StoreHeight::Latest, &path)
+            .await
+            .map_err(store_error(&path))?;
         Ok(Response::new(QueryConnectionResponse {
             connection: conn.map(|c| c.into()),
             proof: vec![],
@@ -284,24 +733,32 @@ impl<Store: IbcStore + 'static> ConnectionQuery for IbcConnectionService<Store>
         let connection_paths = self
             .connection_end_adapter
             .get_paths_by_prefix(&connection_path_prefix)
-            .map_err(Status::internal)?;
+            .await
+            .map_err(store_error(&connection_path_prefix))?;
 
         let mut identified_connections: Vec<RawIdentifiedConnection> =
             Vec::with_capacity(connection_paths.len());
 
         for path in connection_paths.into_iter() {
-            match path.try_into() {
+            match path.clone().try_into() {
                 Ok(IbcPath::Connections(connections_path)) => {
                     let connection_end = self
                         .connection_end_adapter
                         .get_connection_end(StoreHeight::Latest, &connections_path)
-                        .map_err(Status::data_loss)?;
-                    identified_connections.push(
-                        IdentifiedConnectionEnd::new(connections_path.0, connection_end.unwrap())
-                            .into(),
-                    );
+                        .await
+                        .map_err(store_error(&connections_path))?;
+                    match connection_end {
+                        Some(connection_end) => identified_connections.push(
+                            IdentifiedConnectionEnd::new(connections_path.0, connection_end).into(),
+                        ),
+                        None => on_inconsistency(
+                            self.policy,
+                            &connections_path,
+                            "no connection end at path",
+                        )?,
+                    }
                 }
-                _ => panic!("unexpected path"),
+                _ => on_inconsistency(self.policy, &path, "unexpected path")?,
             }
         }
 
@@ -316,16 +773,18 @@ impl<Store: IbcStore + 'static> ConnectionQuery for IbcConnectionService<Store>
         &self,
         request: Request<QueryClientConnectionsRequest>,
     ) -> Result<Response<QueryClientConnectionsResponse>, Status> {
-        let client_id = request
+        let client_id: ClientId = request
             .get_ref()
             .client_id
             .parse()
-            .map_err(|e| Status::invalid_argument(format!("{}", e)))?;
+            .map_err(invalid_field::<ValidationError>("client_id"))?;
+        record_span_field("client_id", &client_id);
         let path = path::ClientConnectionsPath(client_id);
         let connection_ids = self
             .connection_ids_adapter
             .get_connection_ids(StoreHeight::Latest, &path)
-            .unwrap_or_default()
+            .await
+            .map_err(store_error(&path))?
             .iter()
             .map(|conn_id| conn_id.to_string())
             .collect();
@@ -352,43 +811,42 @@ impl<Store: IbcStore + 'static> ConnectionQuery for IbcConnectionService<Store>
     }
 }
 
-pub struct IbcChannelService<Store: IbcStore> {
-    channel_end_adapter: Arc<Store>,
-    packet_commitment_adapter: Arc<Store>,
-    packet_ack_adapter: Arc<Store>,
-    packet_receipt_adapter: Arc<Store>,
+pub struct IbcChannelService<Store: AsyncIbcStore> {
+    channel_end_adapter: Arc<InstrumentedStore<Store>>,
+    packet_commitment_adapter: Arc<InstrumentedStore<Store>>,
+    packet_ack_adapter: Arc<InstrumentedStore<Store>>,
+    packet_receipt_adapter: Arc<InstrumentedStore<Store>>,
+    policy: ConsistencyPolicy,
 }
 
-impl<Store: IbcStore> IbcChannelService<Store> {
-    pub fn new(store: Arc<Store>) -> Self {
+impl<Store: AsyncIbcStore> IbcChannelService<Store> {
+    pub fn new(store: Arc<Store>, policy: ConsistencyPolicy) -> Self {
+        let store = Arc::new(InstrumentedStore::new(store));
         Self {
             channel_end_adapter: Arc::clone(&store),
             packet_commitment_adapter: Arc::clone(&store),
             packet_ack_adapter: Arc::clone(&store),
             packet_receipt_adapter: Arc::clone(&store),
+            policy,
         }
     }
 }
 
 #[tonic::async_trait]
-impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
+impl<Store: AsyncIbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
     async fn channel(
         &self,
         request: Request<QueryChannelRequest>,
     ) -> Result<Response<QueryChannelResponse>, Status> {
         let request = request.into_inner();
-        let port_id = PortId::from_str(&request.port_id)
-            .map_err(|_| Status::invalid_argument("invalid port id"))?;
-        let channel_id = ChannelId::from_str(&request.channel_id)
-            .map_err(|_| Status::invalid_argument("invalid channel id"))?;
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
 
+        let path = path::ChannelEndsPath(port_id, channel_id);
         let channel_opt = self
             .channel_end_adapter
-            .get_channel_end(
-                StoreHeight::Latest,
-                &path::ChannelEndsPath(port_id, channel_id),
-            )
-            .map_err(Status::data_loss)?
+            .get_channel_end(StoreHeight::Latest, &path)
+            .await
+            .map_err(store_error(&path))?
             .map(|channel_end: ChannelEnd| channel_end.into());
 
         Ok(Response::new(QueryChannelResponse {
@@ -410,23 +868,33 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         let channel_paths = self
             .channel_end_adapter
             .get_paths_by_prefix(&channel_path_prefix)
-            .map_err(Status::internal)?;
+            .await
+            .map_err(store_error(&channel_path_prefix))?;
         let mut identified_channels = Vec::with_capacity(channel_paths.len());
 
         for path in channel_paths.into_iter() {
-            match path.try_into() {
+            match path.clone().try_into() {
                 Ok(IbcPath::ChannelEnds(channels_path)) => {
                     let channel_end = self
                         .channel_end_adapter
                         .get_channel_end(StoreHeight::Latest, &channels_path)
-                        .map_err(Status::data_loss)?
-                        .expect("channel path returned by get_keys() had no associated channel");
-                    identified_channels.push(
-                        IdentifiedChannelEnd::new(channels_path.0, channels_path.1, channel_end)
+                        .await
+                        .map_err(store_error(&channels_path))?;
+                    match channel_end {
+                        Some(channel_end) => identified_channels.push(
+                            IdentifiedChannelEnd::new(
+                                channels_path.0,
+                                channels_path.1,
+                                channel_end,
+                            )
                             .into(),
-                    );
+                        ),
+                        None => {
+                            on_inconsistency(self.policy, &channels_path, "no channel end at path")?
+                        }
+                    }
                 }
-                _ => panic!("unexpected path"),
+                _ => on_inconsistency(self.policy, &path, "unexpected path")?,
             }
         }
 
@@ -435,7 +903,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             pagination: None,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.channel_end_adapter.current_height(),
+                revision_height: self.channel_end_adapter.current_height().await,
             }),
         }))
     }
@@ -447,31 +915,59 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         request: Request<QueryConnectionChannelsRequest>,
     ) -> Result<Response<QueryConnectionChannelsResponse>, Status> {
         let conn_id = ConnectionId::from_str(&request.get_ref().connection)
-            .map_err(|_| Status::invalid_argument("invalid connection id"))?;
+            .map_err(invalid_field("connection"))?;
+        record_span_field("connection_id", &conn_id);
 
-        let path = "channelEnds"
-            .to_owned()
-            .try_into()
-            .expect("'commitments/ports' expected to be a valid Path");
-
-        let keys = self
+        let indexed_paths = self
             .channel_end_adapter
-            .get_paths_by_prefix(&path)
-            .map_err(Status::internal)?;
-        let mut identified_channels = Vec::with_capacity(keys.len());
+            .get_channel_paths_by_connection(StoreHeight::Latest, &conn_id)
+            .await
+            .map_err(|e| e.into_status_with([("connection_id", conn_id.to_string())]))?;
+
+        let indexed = indexed_paths.is_some();
+        let channel_paths = match indexed_paths {
+            Some(paths) => paths,
+            None => {
+                let path = "channelEnds"
+                    .to_owned()
+                    .try_into()
+                    .expect("'channelEnds' expected to be a valid Path");
+
+                self.channel_end_adapter
+                    .get_paths_by_prefix(&path)
+                    .await
+                    .map_err(store_error(&path))?
+                    .into_iter()
+                    .filter_map(|path| match path.try_into() {
+                        Ok(IbcPath::ChannelEnds(p)) => Some(p),
+                        _ => None,
+                    })
+                    .collect()
+            }
+        };
+        let mut identified_channels = Vec::with_capacity(channel_paths.len());
 
-        for path in keys.into_iter() {
-            if let Ok(IbcPath::ChannelEnds(path)) = path.try_into() {
-                if let Some(channel_end) = self
-                    .channel_end_adapter
-                    .get_channel_end(StoreHeight::Latest, &path)
-                    .map_err(Status::data_loss)?
-                {
+        // The connection hop is checked even for indexed paths, so that a stale
+        // index can never leak a channel of another connection. An indexed
+        // path without a channel end is a stale index entry and is skipped
+        // under either policy; a listed path without one is an inconsistency.
+        for path in channel_paths.into_iter() {
+            match self
+                .channel_end_adapter
+                .get_channel_end(StoreHeight::Latest, &path)
+                .await
+                .map_err(store_error(&path))?
+            {
+                Some(channel_end) => {
                     if channel_end.connection_hops.first() == Some(&conn_id) {
                         identified_channels
                             .push(IdentifiedChannelEnd::new(path.0, path.1, channel_end).into());
                     }
                 }
+                None if indexed => {
+                    log::debug!("Skipping stale channel index entry {}", path);
+                }
+                None => on_inconsistency(self.policy, &path, "no channel end at path")?,
             }
         }
 
@@ -480,7 +976,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             pagination: None,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.channel_end_adapter.current_height(),
+                revision_height: self.channel_end_adapter.current_height().await,
             }),
         }))
     }
@@ -517,10 +1013,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         request: Request<QueryPacketCommitmentsRequest>,
     ) -> Result<Response<QueryPacketCommitmentsResponse>, Status> {
         let request = request.into_inner();
-        let port_id = PortId::from_str(&request.port_id)
-            .map_err(|_| Status::invalid_argument("invalid port id"))?;
-        let channel_id = ChannelId::from_str(&request.channel_id)
-            .map_err(|_| Status::invalid_argument("invalid channel id"))?;
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
 
         let commitment_paths = {
             let prefix: Path = String::from("commitments/ports")
@@ -528,7 +1021,8 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
                 .expect("'commitments/ports' expected to be a valid Path");
             self.packet_commitment_adapter
                 .get_paths_by_prefix(&prefix)
-                .map_err(Status::internal)?
+                .await
+                .map_err(store_error(&prefix))?
         };
 
         let matching_commitment_paths = |path: Path| -> Option<path::CommitmentsPath> {
@@ -548,11 +1042,18 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             .into_iter()
             .filter_map(matching_commitment_paths)
         {
-            let commitment = self
+            let commitment = match self
                 .packet_commitment_adapter
                 .get_packet_commitment(StoreHeight::Latest, &path)
-                .map_err(Status::data_loss)?
-                .unwrap();
+                .await
+                .map_err(store_error(&path))?
+            {
+                Some(commitment) => commitment,
+                None => {
+                    on_inconsistency(self.policy, &path, "no packet commitment at path")?;
+                    continue;
+                }
+            };
             let data = commitment.into_vec();
             if !data.is_empty() {
                 packet_states.push(PacketState {
@@ -569,7 +1070,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             pagination: None,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.packet_commitment_adapter.current_height(),
+                revision_height: self.packet_commitment_adapter.current_height().await,
             }),
         }))
     }
@@ -597,10 +1098,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         request: Request<QueryPacketAcknowledgementsRequest>,
     ) -> Result<Response<QueryPacketAcknowledgementsResponse>, Status> {
         let request = request.into_inner();
-        let port_id = PortId::from_str(&request.port_id)
-            .map_err(|_| Status::invalid_argument("invalid port id"))?;
-        let channel_id = ChannelId::from_str(&request.channel_id)
-            .map_err(|_| Status::invalid_argument("invalid channel id"))?;
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
 
         let ack_paths = {
             let prefix: Path = String::from("acks/ports")
@@ -608,7 +1106,8 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
                 .expect("'acks/ports' expected to be a valid Path");
             self.packet_ack_adapter
                 .get_paths_by_prefix(&prefix)
-                .map_err(Status::internal)?
+                .await
+                .map_err(store_error(&prefix))?
         };
 
         let matching_ack_paths = |path: Path| -> Option<path::AcksPath> {
@@ -620,23 +1119,36 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             }
         };
 
+        let ack_paths: Vec<path::AcksPath> = ack_paths
+            .into_iter()
+            .filter_map(matching_ack_paths)
+            .collect();
+        let acks_prefix = format!("acks/ports/{}/channels/{}", port_id, channel_id);
+        let commitments = self
+            .packet_ack_adapter
+            .get_acknowledgement_commitments(StoreHeight::Latest, &ack_paths)
+            .await
+            .map_err(store_error(&acks_prefix))?;
+        let commitments = check_batch(self.policy, &acks_prefix, commitments, ack_paths.len())?;
+
         let mut packet_states = Vec::with_capacity(ack_paths.len());
 
-        for path in ack_paths.into_iter().filter_map(matching_ack_paths) {
-            if let Some(commitment) = self
-                .packet_ack_adapter
-                .get_acknowledgement_commitment(StoreHeight::Latest, &path)
-                .map_err(Status::data_loss)?
-            {
-                let data = commitment.into_vec();
-                if !data.is_empty() {
-                    packet_states.push(PacketState {
-                        port_id: path.port_id.to_string(),
-                        channel_id: path.channel_id.to_string(),
-                        sequence: path.sequence.into(),
-                        data,
-                    });
+        for (path, commitment) in ack_paths.into_iter().zip(commitments) {
+            let commitment = match commitment {
+                Some(commitment) => commitment,
+                None => {
+                    on_inconsistency(self.policy, &path, "no acknowledgement at path")?;
+                    continue;
                 }
+            };
+            let data = commitment.into_vec();
+            if !data.is_empty() {
+                packet_states.push(PacketState {
+                    port_id: path.port_id.to_string(),
+                    channel_id: path.channel_id.to_string(),
+                    sequence: path.sequence.into(),
+                    data,
+                });
             }
         }
 
@@ -645,7 +1157,7 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
             pagination: None,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.packet_ack_adapter.current_height(),
+                revision_height: self.packet_ack_adapter.current_height().await,
             }),
         }))
     }
@@ -661,34 +1173,43 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         request: Request<QueryUnreceivedPacketsRequest>,
     ) -> Result<Response<QueryUnreceivedPacketsResponse>, Status> {
         let request = request.into_inner();
-        let port_id = PortId::from_str(&request.port_id)
-            .map_err(|_| Status::invalid_argument("invalid port id"))?;
-        let channel_id = ChannelId::from_str(&request.channel_id)
-            .map_err(|_| Status::invalid_argument("invalid channel id"))?;
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
         let sequences_to_check: Vec<u64> = request.packet_commitment_sequences;
+        record_span_field("sequences", &sequences_to_check.len());
+
+        let mut receipts_paths = Vec::with_capacity(sequences_to_check.len());
+        for seq in &sequences_to_check {
+            receipts_paths.push(path::ReceiptsPath {
+                port_id: port_id.clone(),
+                channel_id: channel_id.clone(),
+                sequence: parse_sequence(*seq)?,
+            });
+        }
+        let receipts_prefix = format!("receipts/ports/{}/channels/{}", port_id, channel_id);
+        let packet_receipts = self
+            .packet_receipt_adapter
+            .get_opts(StoreHeight::Latest, &receipts_paths)
+            .await
+            .map_err(store_error(&receipts_prefix))?;
+        let packet_receipts = check_batch(
+            self.policy,
+            &receipts_prefix,
+            packet_receipts,
+            receipts_paths.len(),
+        )?;
 
         let unreceived_sequences: Vec<u64> = sequences_to_check
             .into_iter()
-            .filter(|seq| {
-                let receipts_path = path::ReceiptsPath {
-                    port_id: port_id.clone(),
-                    channel_id: channel_id.clone(),
-                    sequence: Sequence::from(*seq),
-                };
-                let packet_receipt: Option<()> = self
-                    .packet_receipt_adapter
-                    .get_opt(StoreHeight::Latest, &receipts_path)
-                    .ok()
-                    .flatten();
-                packet_receipt.is_none()
-            })
+            .zip(packet_receipts)
+            .filter(|(_, packet_receipt)| packet_receipt.is_none())
+            .map(|(seq, _)| seq)
             .collect();
 
         Ok(Response::new(QueryUnreceivedPacketsResponse {
             sequences: unreceived_sequences,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.packet_receipt_adapter.current_height(),
+                revision_height: self.packet_receipt_adapter.current_height().await,
             }),
         }))
     }
@@ -700,37 +1221,46 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         request: Request<QueryUnreceivedAcksRequest>,
     ) -> Result<Response<QueryUnreceivedAcksResponse>, Status> {
         let request = request.into_inner();
-        let port_id = PortId::from_str(&request.port_id)
-            .map_err(|_| Status::invalid_argument("invalid port id"))?;
-        let channel_id = ChannelId::from_str(&request.channel_id)
-            .map_err(|_| Status::invalid_argument("invalid channel id"))?;
+        let (port_id, channel_id) = parse_channel(&request.port_id, &request.channel_id)?;
         let sequences_to_check: Vec<u64> = request.packet_ack_sequences;
+        record_span_field("sequences", &sequences_to_check.len());
+
+        // To check if we received an acknowledgement, we check if we still have the
+        // sent packet commitment (upon receiving an ack, the sent packet commitment
+        // is deleted).
+        let mut commitments_paths = Vec::with_capacity(sequences_to_check.len());
+        for seq in &sequences_to_check {
+            commitments_paths.push(path::CommitmentsPath {
+                port_id: port_id.clone(),
+                channel_id: channel_id.clone(),
+                sequence: parse_sequence(*seq)?,
+            });
+        }
+        let commitments_prefix = format!("commitments/ports/{}/channels/{}", port_id, channel_id);
+        let packet_commitments = self
+            .packet_commitment_adapter
+            .get_packet_commitments(StoreHeight::Latest, &commitments_paths)
+            .await
+            .map_err(store_error(&commitments_prefix))?;
+        let packet_commitments = check_batch(
+            self.policy,
+            &commitments_prefix,
+            packet_commitments,
+            commitments_paths.len(),
+        )?;
 
         let unreceived_sequences: Vec<u64> = sequences_to_check
             .into_iter()
-            .filter(|seq| {
-                // To check if we received an acknowledgement, we check if we still have the
-                // sent packet commitment (upon receiving an ack, the sent
-                // packet commitment is deleted).
-                let commitments_path = path::CommitmentsPath {
-                    port_id: port_id.clone(),
-                    channel_id: channel_id.clone(),
-                    sequence: Sequence::from(*seq),
-                };
-
-                self.packet_commitment_adapter
-                    .get_packet_commitment(StoreHeight::Latest, &commitments_path)
-                    .ok()
-                    .flatten()
-                    .is_some()
-            })
+            .zip(packet_commitments)
+            .filter(|(_, commitment)| commitment.is_some())
+            .map(|(seq, _)| seq)
             .collect();
 
         Ok(Response::new(QueryUnreceivedAcksResponse {
             sequences: unreceived_sequences,
             height: Some(Height {
                 revision_number: CHAIN_REVISION_NUMBER,
-                revision_height: self.packet_commitment_adapter.current_height(),
+                revision_height: self.packet_commitment_adapter.current_height().await,
             }),
         }))
     }
@@ -744,3 +1274,247 @@ impl<Store: IbcStore + 'static> ChannelQuery for IbcChannelService<Store> {
         todo!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Endpoint;
+    use tonic_reflection::proto::server_reflection_client::ServerReflectionClient;
+    use tonic_reflection::proto::server_reflection_request::MessageRequest;
+    use tonic_reflection::proto::server_reflection_response::MessageResponse;
+    use tonic_reflection::proto::ServerReflectionRequest;
+
+    use ibc::core::ics02_client::client_state::ClientState;
+    use ibc::core::ics02_client::consensus_state::ConsensusState;
+    use ibc::core::ics04_channel::commitment::PacketCommitment;
+    use ibc_proto::google::protobuf::FileDescriptorSet;
+    use prost::Message;
+
+    use super::*;
+    use crate::{BlockingIbcStore, IbcStore};
+
+    /// A store whose batched receipt getter drops the last entry, whose
+    /// batched commitment getter fails, and whose channel index lists a
+    /// channel end it does not have.
+    struct ShortBatchStore;
+
+    impl IbcStore for ShortBatchStore {
+        fn get_client_state(
+            &self,
+            _height: StoreHeight,
+            _path: &path::ClientStatePath,
+        ) -> crate::Result<Option<Box<dyn ClientState>>> {
+            Ok(None)
+        }
+
+        fn get_consensus_state(
+            &self,
+            _height: StoreHeight,
+            _path: &path::ClientConsensusStatePath,
+        ) -> crate::Result<Option<Box<dyn ConsensusState>>> {
+            Ok(None)
+        }
+
+        fn get_opts(
+            &self,
+            _height: StoreHeight,
+            paths: &[path::ReceiptsPath],
+        ) -> crate::Result<Vec<Option<()>>> {
+            Ok(vec![Some(()); paths.len() - 1])
+        }
+
+        fn get_packet_commitments(
+            &self,
+            _height: StoreHeight,
+            _paths: &[path::CommitmentsPath],
+        ) -> crate::Result<Vec<Option<PacketCommitment>>> {
+            Err(ServerError::Storage("disk failure".to_owned()))
+        }
+
+        fn get_raw(&self, _height: StoreHeight, _path: &Path) -> crate::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn get_channel_paths_by_connection(
+            &self,
+            _height: StoreHeight,
+            _conn_id: &ConnectionId,
+        ) -> crate::Result<Option<Vec<path::ChannelEndsPath>>> {
+            Ok(Some(vec![path::ChannelEndsPath(
+                PortId::transfer(),
+                ChannelId::new(0),
+            )]))
+        }
+
+        fn get_paths_by_prefix(&self, _key_prefix: &Path) -> crate::Result<Vec<Path>> {
+            Ok(vec![])
+        }
+
+        fn current_height(&self) -> u64 {
+            1
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_length_mismatch() {
+        let request = || {
+            Request::new(QueryUnreceivedPacketsRequest {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-0".to_owned(),
+                packet_commitment_sequences: vec![1, 2],
+            })
+        };
+
+        let store = Arc::new(BlockingIbcStore::new(ShortBatchStore));
+        let strict = IbcChannelService::new(Arc::clone(&store), ConsistencyPolicy::Strict);
+        let status = strict.unreceived_packets(request()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DataLoss);
+
+        // The receipt missing from the batch counts as absent.
+        let lenient = IbcChannelService::new(store, ConsistencyPolicy::Lenient);
+        let response = lenient.unreceived_packets(request()).await.unwrap();
+        assert_eq!(response.into_inner().sequences, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_store_error() {
+        let store = Arc::new(BlockingIbcStore::new(ShortBatchStore));
+        let service = IbcChannelService::new(store, ConsistencyPolicy::Lenient);
+        let status = service
+            .unreceived_acks(Request::new(QueryUnreceivedAcksRequest {
+                port_id: "transfer".to_owned(),
+                channel_id: "channel-0".to_owned(),
+                packet_ack_sequences: vec![1, 2],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(status.message(), "storage error: disk failure");
+    }
+
+    #[tokio::test]
+    async fn test_stale_channel_index() {
+        let store = Arc::new(BlockingIbcStore::new(ShortBatchStore));
+        let service = IbcChannelService::new(store, ConsistencyPolicy::Strict);
+        let response = service
+            .connection_channels(Request::new(QueryConnectionChannelsRequest {
+                connection: "connection-0".to_owned(),
+                pagination: None,
+            }))
+            .await
+            .unwrap();
+        assert!(response.into_inner().channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_start_stops_background_tasks() {
+        let metrics_addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let service = crate::IbcGrpcServiceBuilder::new("127.0.0.1:0")
+            .metrics_addr(metrics_addr.to_string())
+            .abci_query_addr(taken.local_addr().unwrap().to_string())
+            .build(BlockingIbcStore::new(ShortBatchStore))
+            .unwrap();
+        assert!(service.run().await.is_err());
+
+        // The metrics endpoint was bound before the ABCI endpoint failed.
+        tokio::task::yield_now().await;
+        assert!(TcpListener::bind(metrics_addr).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reflection_lists_mounted_services() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = reflection_service(
+            &[
+                file_descriptor_set!("client"),
+                file_descriptor_set!("transfer"),
+                file_descriptor_set!("tendermint"),
+            ],
+            &[],
+        )
+        .unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let channel = Endpoint::from_shared(format!("http://{}", addr))
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        let mut client = ServerReflectionClient::new(channel);
+        let request = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(MessageRequest::ListServices(String::new())),
+        };
+        let response = client
+            .server_reflection_info(futures::stream::iter([request]))
+            .await
+            .unwrap()
+            .into_inner()
+            .message()
+            .await
+            .unwrap()
+            .unwrap();
+
+        let services: Vec<String> = match response.message_response {
+            Some(MessageResponse::ListServicesResponse(list)) => list
+                .service
+                .into_iter()
+                .map(|service| service.name)
+                .collect(),
+            other => panic!("unexpected response {:?}", other),
+        };
+        for name in [
+            "ibc.core.client.v1.Query",
+            "ibc.applications.transfer.v1.Query",
+            "cosmos.base.tendermint.v1beta1.Service",
+            "grpc.reflection.v1alpha.ServerReflection",
+        ] {
+            assert!(services.iter().any(|service| service == name), "{}", name);
+        }
+        // Services that are not mounted are not described.
+        assert!(!services
+            .iter()
+            .any(|service| service == "ibc.core.connection.v1.Query"));
+    }
+
+    #[test]
+    fn test_reflection_describes_every_service() {
+        let sets = [
+            file_descriptor_set!("client").as_slice(),
+            file_descriptor_set!("connection"),
+            file_descriptor_set!("channel"),
+            file_descriptor_set!("transfer"),
+            file_descriptor_set!("ica_controller"),
+            file_descriptor_set!("ica_host"),
+            file_descriptor_set!("fee"),
+            file_descriptor_set!("tendermint"),
+            file_descriptor_set!("staking"),
+            file_descriptor_set!("auth"),
+        ];
+        reflection_service(&sets, &[]).unwrap();
+
+        // Reflection keeps the first file registered under a name, so the
+        // files the sets share, such as the pagination protos, must agree.
+        let mut files = HashMap::new();
+        for set in sets {
+            for file in FileDescriptorSet::decode(set).unwrap().file {
+                let name = file.name.clone().unwrap();
+                assert_eq!(files.entry(name).or_insert_with(|| file.clone()), &file);
+            }
+        }
+    }
+}
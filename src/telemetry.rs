@@ -0,0 +1,144 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::path::Path;
+use std::task::{Context, Poll};
+
+use http::{HeaderMap, Request};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::sdk::export::trace::stdout;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::trace::Tracer;
+use tower::{Layer, Service};
+use tracing::field::{display, Empty};
+use tracing::instrument::{Instrument, Instrumented};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::error::ServerError;
+
+/// Records `value` as `field` of the span of the current request. Only the
+/// fields declared by [`TraceLayer`] are recorded.
+pub(crate) fn record_span_field(field: &'static str, value: &dyn Display) {
+    tracing::Span::current().record(field, display(value));
+}
+
+/// Exports the spans of all requests with `tracer`, e.g. the tracer of an
+/// OTLP or Jaeger pipeline. This installs the global `tracing` subscriber, so
+/// it can only be called once per process.
+pub fn install_tracer(tracer: Tracer) -> crate::Result<()> {
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|e| ServerError::Config(format!("failed to install tracer: {}", e)))
+}
+
+/// Prints the spans of all requests to stdout.
+pub fn install_stdout_tracer() -> crate::Result<()> {
+    install_tracer(stdout::new_pipeline().install_simple())
+}
+
+/// Appends the spans of all requests to the file at `path`.
+pub fn install_file_tracer(path: impl AsRef<Path>) -> crate::Result<()> {
+    let path = path.as_ref();
+    let file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| ServerError::Config(format!("failed to open {}: {}", path.display(), e)))?;
+    install_tracer(stdout::new_pipeline().with_writer(file).install_simple())
+}
+
+/// A layer running every request in a span named after its method, child of
+/// the span of the W3C `traceparent` metadata if any. Handlers fill in the
+/// identifiers they parse and the store height they read at.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = TraceService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceService { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for TraceService<S>
+where
+    S: Service<Request<ReqBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Instrumented<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let span = request_span(req.headers(), req.uri().path());
+        let _entered = span.enter();
+        self.inner.call(req).instrument(span.clone())
+    }
+}
+
+/// The span of a request for `method`, child of the span of the W3C
+/// `traceparent` header if any.
+pub(crate) fn request_span(headers: &HeaderMap, method: &str) -> tracing::Span {
+    let span = tracing::info_span!(
+        "grpc_request",
+        method = %method,
+        client_id = Empty,
+        connection_id = Empty,
+        port_id = Empty,
+        channel_id = Empty,
+        sequences = Empty,
+        height = Empty,
+    );
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+    span.set_parent(parent);
+    span
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::TraceContextExt;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+                .parse()
+                .unwrap(),
+        );
+
+        let cx = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+        let span = cx.span();
+        let span_context = span.span_context();
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::sync::Arc;
+
+use ibc_proto::cosmos::base::tendermint::v1beta1::{
+    service_server::Service as TendermintQuery, AbciQueryRequest, AbciQueryResponse,
+    GetBlockByHeightRequest, GetBlockByHeightResponse, GetLatestBlockRequest,
+    GetLatestBlockResponse, GetLatestValidatorSetRequest, GetLatestValidatorSetResponse,
+    GetNodeInfoRequest, GetNodeInfoResponse, GetSyncingRequest, GetSyncingResponse,
+    GetValidatorSetByHeightRequest, GetValidatorSetByHeightResponse, Module, VersionInfo,
+};
+use tendermint_proto::p2p::DefaultNodeInfo;
+use tendermint_proto::types::{Block, BlockId, Header};
+use tonic::{Request, Response, Status};
+
+use crate::abci_query::AbciQuery;
+use crate::instrument::InstrumentedStore;
+use crate::telemetry::record_span_field;
+use crate::{AsyncIbcStore, ServerError};
+
+/// What the tendermint service reports about the chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NodeInfo {
+    pub(crate) chain_id: String,
+    /// The version of the application.
+    pub(crate) version: String,
+    pub(crate) versions: NodeVersions,
+}
+
+/// The versions of the node software the tendermint service reports.
+/// Relayers such as Hermes check the Cosmos SDK and ibc-go versions for
+/// compatibility. They default to the versions ibc-proto 0.21 was generated
+/// from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NodeVersions {
+    pub(crate) tendermint: String,
+    pub(crate) cosmos_sdk: String,
+    pub(crate) ibc_go: String,
+}
+
+impl Default for NodeVersions {
+    fn default() -> Self {
+        Self {
+            tendermint: "0.34.21".to_owned(),
+            cosmos_sdk: "v0.46.1".to_owned(),
+            ibc_go: "v5.0.0".to_owned(),
+        }
+    }
+}
+
+impl NodeInfo {
+    /// The application version, with the Cosmos SDK and ibc-go as the Go
+    /// modules it was built with.
+    fn version_info(&self) -> VersionInfo {
+        let versions = &self.versions;
+        VersionInfo {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            app_name: env!("CARGO_PKG_NAME").to_owned(),
+            version: self.version.clone(),
+            build_deps: vec![
+                Module {
+                    path: "github.com/cosmos/cosmos-sdk".to_owned(),
+                    version: versions.cosmos_sdk.clone(),
+                    sum: String::new(),
+                },
+                Module {
+                    path: ibc_go_module_path(&versions.ibc_go),
+                    version: versions.ibc_go.clone(),
+                    sum: String::new(),
+                },
+            ],
+            cosmos_sdk_version: versions.cosmos_sdk.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The Go module path of ibc-go at `version`, which carries the major
+/// version from v2 on, e.g. `github.com/cosmos/ibc-go/v5`.
+fn ibc_go_module_path(version: &str) -> String {
+    let major = version
+        .trim_start_matches('v')
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u64>().ok());
+    match major {
+        Some(major) if major >= 2 => format!("github.com/cosmos/ibc-go/v{}", major),
+        _ => "github.com/cosmos/ibc-go".to_owned(),
+    }
+}
+
+/// A block that only carries a header with the chain id and `height`, which
+/// is all relayers read from it.
+fn block(chain_id: &str, height: u64) -> Block {
+    Block {
+        header: Some(Header {
+            chain_id: chain_id.to_owned(),
+            height: i64::try_from(height).unwrap_or(i64::MAX),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Serves the parts of `cosmos.base.tendermint.v1beta1.Service` relayers
+/// such as Hermes query before the IBC services: the node info and the
+/// latest height, plus ABCI queries of the IBC store keys. The blocks have
+/// no hash, time or transactions, and the validator sets are not
+/// implemented.
+pub struct IbcTendermintService<Store: AsyncIbcStore> {
+    store: Arc<InstrumentedStore<Store>>,
+    node_info: NodeInfo,
+}
+
+impl<Store: AsyncIbcStore> IbcTendermintService<Store> {
+    pub(crate) fn new(store: Arc<Store>, node_info: NodeInfo) -> Self {
+        Self {
+            store: Arc::new(InstrumentedStore::new(store)),
+            node_info,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<Store: AsyncIbcStore + 'static> TendermintQuery for IbcTendermintService<Store> {
+    async fn get_node_info(
+        &self,
+        _request: Request<GetNodeInfoRequest>,
+    ) -> Result<Response<GetNodeInfoResponse>, Status> {
+        Ok(Response::new(GetNodeInfoResponse {
+            default_node_info: Some(DefaultNodeInfo {
+                network: self.node_info.chain_id.clone(),
+                version: self.node_info.versions.tendermint.clone(),
+                moniker: env!("CARGO_PKG_NAME").to_owned(),
+                ..Default::default()
+            }),
+            application_version: Some(self.node_info.version_info()),
+        }))
+    }
+
+    async fn get_syncing(
+        &self,
+        _request: Request<GetSyncingRequest>,
+    ) -> Result<Response<GetSyncingResponse>, Status> {
+        Ok(Response::new(GetSyncingResponse { syncing: false }))
+    }
+
+    async fn get_latest_block(
+        &self,
+        _request: Request<GetLatestBlockRequest>,
+    ) -> Result<Response<GetLatestBlockResponse>, Status> {
+        let height = self.store.current_height().await;
+        Ok(Response::new(GetLatestBlockResponse {
+            block_id: Some(BlockId::default()),
+            block: Some(block(&self.node_info.chain_id, height)),
+            sdk_block: None,
+        }))
+    }
+
+    async fn get_block_by_height(
+        &self,
+        request: Request<GetBlockByHeightRequest>,
+    ) -> Result<Response<GetBlockByHeightResponse>, Status> {
+        let height = u64::try_from(request.get_ref().height)
+            .ok()
+            .filter(|height| *height > 0)
+            .ok_or_else(|| {
+                ServerError::InvalidArgument(format!("block height {}", request.get_ref().height))
+                    .into_status_with([("field", "height".to_owned())])
+            })?;
+        record_span_field("height", &height);
+        if height > self.store.current_height().await {
+            return Err(ServerError::HeightNotAvailable(height).into());
+        }
+
+        Ok(Response::new(GetBlockByHeightResponse {
+            block_id: Some(BlockId::default()),
+            block: Some(block(&self.node_info.chain_id, height)),
+            sdk_block: None,
+        }))
+    }
+
+    async fn get_latest_validator_set(
+        &self,
+        _request: Request<GetLatestValidatorSetRequest>,
+    ) -> Result<Response<GetLatestValidatorSetResponse>, Status> {
+        Err(ServerError::Unimplemented("validator sets".to_owned()).into())
+    }
+
+    async fn get_validator_set_by_height(
+        &self,
+        _request: Request<GetValidatorSetByHeightRequest>,
+    ) -> Result<Response<GetValidatorSetByHeightResponse>, Status> {
+        Err(ServerError::Unimplemented("validator sets".to_owned()).into())
+    }
+
+    async fn abci_query(
+        &self,
+        request: Request<AbciQueryRequest>,
+    ) -> Result<Response<AbciQueryResponse>, Status> {
+        let request = request.into_inner();
+        let height = u64::try_from(request.height).map_err(|_| {
+            ServerError::InvalidArgument(format!("query height {}", request.height))
+                .into_status_with([("field", "height".to_owned())])
+        })?;
+        let query = AbciQuery::new(&request.path, request.data, height, request.prove);
+        Ok(Response::new(query.run(self.store.as_ref()).await))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibc::core::ics02_client::{client_state::ClientState, consensus_state::ConsensusState};
+    use ibc::core::ics24_host::path::{ClientConsensusStatePath, ClientStatePath};
+
+    use super::*;
+    use crate::types::{Path, StoreHeight};
+    use crate::{BlockingIbcStore, IbcStore};
+
+    /// A store holding the same raw value at every path.
+    struct ValueStore;
+
+    impl IbcStore for ValueStore {
+        fn get_client_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientStatePath,
+        ) -> crate::Result<Option<Box<dyn ClientState>>> {
+            Ok(None)
+        }
+
+        fn get_consensus_state(
+            &self,
+            _height: StoreHeight,
+            _path: &ClientConsensusStatePath,
+        ) -> crate::Result<Option<Box<dyn ConsensusState>>> {
+            Ok(None)
+        }
+
+        fn get_paths_by_prefix(&self, _key_prefix: &Path) -> crate::Result<Vec<Path>> {
+            Ok(vec![])
+        }
+
+        fn current_height(&self) -> u64 {
+            5
+        }
+
+        fn get_raw(&self, _height: StoreHeight, _path: &Path) -> crate::Result<Option<Vec<u8>>> {
+            Ok(Some(vec![1, 2, 3]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_abci_query() {
+        let node_info = NodeInfo {
+            chain_id: "axon-1".to_owned(),
+            version: "0.3.0".to_owned(),
+            versions: NodeVersions::default(),
+        };
+        let service =
+            IbcTendermintService::new(Arc::new(BlockingIbcStore::new(ValueStore)), node_info);
+        let request = |path: &str, height| {
+            Request::new(AbciQueryRequest {
+                data: b"clients/07-tendermint-0/clientState".to_vec(),
+                path: path.to_owned(),
+                height,
+                prove: false,
+            })
+        };
+
+        let response = service
+            .abci_query(request("/store/ibc/key", 0))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.code, 0);
+        assert_eq!(response.value, vec![1, 2, 3]);
+        assert_eq!(response.height, 5);
+
+        let response = service
+            .abci_query(request("/store/bank/key", 0))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.code, 1);
+
+        let status = service
+            .abci_query(request("/store/ibc/key", -1))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_version_info() {
+        let node_info = NodeInfo {
+            chain_id: "axon-1".to_owned(),
+            version: "0.3.0".to_owned(),
+            versions: NodeVersions::default(),
+        };
+        let version_info = node_info.version_info();
+        assert_eq!(version_info.version, "0.3.0");
+        assert_eq!(version_info.cosmos_sdk_version, "v0.46.1");
+        let deps: Vec<_> = version_info
+            .build_deps
+            .iter()
+            .map(|module| (module.path.as_str(), module.version.as_str()))
+            .collect();
+        assert_eq!(
+            deps,
+            [
+                ("github.com/cosmos/cosmos-sdk", "v0.46.1"),
+                ("github.com/cosmos/ibc-go/v5", "v5.0.0"),
+            ]
+        );
+
+        assert_eq!(ibc_go_module_path("v1.2.0"), "github.com/cosmos/ibc-go");
+        assert_eq!(ibc_go_module_path("7.0.0"), "github.com/cosmos/ibc-go/v7");
+    }
+}
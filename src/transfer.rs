@@ -0,0 +1,189 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ibc::applications::transfer::denom::PrefixedDenom;
+use ibc_proto::ibc::applications::transfer::v1::{
+    query_server::Query as TransferQuery, Params, QueryDenomHashRequest, QueryDenomHashResponse,
+    QueryDenomTraceRequest, QueryDenomTraceResponse, QueryDenomTracesRequest,
+    QueryDenomTracesResponse, QueryEscrowAddressRequest, QueryEscrowAddressResponse,
+    QueryParamsRequest, QueryParamsResponse,
+};
+use tonic::{Request, Response, Status};
+
+use ibc::core::ics24_host::identifier::{ChannelId, PortId};
+
+use crate::auth::sha256_hex;
+use crate::instrument::timed_blocking;
+use crate::pagination::paginate;
+use crate::service::{invalid_field, parse_channel};
+use crate::types::TransferParams;
+use crate::{ServerError, StoreHeight};
+
+/// The ICS-20 transfer module state of the chain, queried by the transfer
+/// query service. It is an extension of [`IbcStore`](crate::IbcStore), given
+/// to [`IbcGrpcServiceBuilder::transfer_store`](crate::IbcGrpcServiceBuilder::transfer_store)
+/// by the chains running the transfer module. Calls run on the blocking pool.
+pub trait IbcTransferStore: Sync + Send {
+    /// Returns the denom trace whose hash is `hash`, the uppercase hex
+    /// SHA-256 of `{trace_path}/{base_denom}`.
+    fn get_denom_trace(
+        &self,
+        height: StoreHeight,
+        hash: &str,
+    ) -> crate::Result<Option<PrefixedDenom>>;
+
+    fn get_denom_traces(&self, height: StoreHeight) -> crate::Result<Vec<PrefixedDenom>>;
+
+    fn get_transfer_params(&self, height: StoreHeight) -> crate::Result<TransferParams>;
+
+    /// Returns the address of the account escrowing the tokens sent over
+    /// `channel_id`, e.g. the ADR-028 address of the Cosmos SDK.
+    fn get_escrow_address(
+        &self,
+        height: StoreHeight,
+        port_id: &PortId,
+        channel_id: &ChannelId,
+    ) -> crate::Result<String>;
+}
+
+/// A transfer store shared by the transfer query services.
+#[derive(Clone)]
+pub(crate) struct SharedTransferStore(pub(crate) Arc<dyn IbcTransferStore>);
+
+impl std::fmt::Debug for SharedTransferStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedTransferStore")
+    }
+}
+
+/// The hash identifying a denom trace, as in the `ibc/{hash}` denom of the
+/// vouchers minted for it.
+fn denom_hash(denom: &PrefixedDenom) -> String {
+    sha256_hex(denom.to_string().as_bytes()).to_uppercase()
+}
+
+/// Parses the `hash` of a DenomTrace request, given either as a hex hash or
+/// as an `ibc/{hash}` denom.
+fn parse_denom_hash(hash: &str) -> Result<String, ServerError> {
+    let hex = hash.strip_prefix("ibc/").unwrap_or(hash);
+    if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ServerError::InvalidArgument(format!(
+            "denom trace hash {:?}",
+            hash
+        )));
+    }
+    Ok(hex.to_uppercase())
+}
+
+/// Serves `ibc.applications.transfer.v1.Query` from an [`IbcTransferStore`].
+pub struct IbcTransferService {
+    store: Arc<dyn IbcTransferStore>,
+}
+
+impl IbcTransferService {
+    pub(crate) fn new(store: SharedTransferStore) -> Self {
+        Self { store: store.0 }
+    }
+}
+
+#[tonic::async_trait]
+impl TransferQuery for IbcTransferService {
+    async fn denom_trace(
+        &self,
+        request: Request<QueryDenomTraceRequest>,
+    ) -> Result<Response<QueryDenomTraceResponse>, Status> {
+        let hash = parse_denom_hash(&request.get_ref().hash).map_err(invalid_field("hash"))?;
+        let not_found = format!("denom trace {}", hash);
+        let denom = timed_blocking(&self.store, "get_denom_trace", move |store| {
+            store.get_denom_trace(StoreHeight::Latest, &hash)
+        })
+        .await?
+        .ok_or(ServerError::NotFound(not_found))?;
+        Ok(Response::new(QueryDenomTraceResponse {
+            denom_trace: Some(denom.into()),
+        }))
+    }
+
+    async fn denom_traces(
+        &self,
+        request: Request<QueryDenomTracesRequest>,
+    ) -> Result<Response<QueryDenomTracesResponse>, Status> {
+        let denom_traces = timed_blocking(&self.store, "get_denom_traces", |store| {
+            store.get_denom_traces(StoreHeight::Latest)
+        })
+        .await?;
+        let (denom_traces, pagination) = paginate(denom_traces, request.into_inner().pagination)
+            .map_err(invalid_field("pagination"))?;
+        Ok(Response::new(QueryDenomTracesResponse {
+            denom_traces: denom_traces.into_iter().map(Into::into).collect(),
+            pagination: Some(pagination),
+        }))
+    }
+
+    async fn params(
+        &self,
+        _request: Request<QueryParamsRequest>,
+    ) -> Result<Response<QueryParamsResponse>, Status> {
+        let params = timed_blocking(&self.store, "get_transfer_params", |store| {
+            store.get_transfer_params(StoreHeight::Latest)
+        })
+        .await?;
+        Ok(Response::new(QueryParamsResponse {
+            params: Some(Params {
+                send_enabled: params.send_enabled,
+                receive_enabled: params.receive_enabled,
+            }),
+        }))
+    }
+
+    async fn denom_hash(
+        &self,
+        request: Request<QueryDenomHashRequest>,
+    ) -> Result<Response<QueryDenomHashResponse>, Status> {
+        let denom = PrefixedDenom::from_str(&request.get_ref().trace).map_err(|e| {
+            ServerError::InvalidArgument(format!("denom trace: {}", e))
+                .into_status_with([("field", "trace".to_owned())])
+        })?;
+        let hash = denom_hash(&denom);
+        let lookup = hash.clone();
+        timed_blocking(&self.store, "get_denom_trace", move |store| {
+            store.get_denom_trace(StoreHeight::Latest, &lookup)
+        })
+        .await?
+        .ok_or_else(|| ServerError::NotFound(format!("denom trace {}", denom)))?;
+        Ok(Response::new(QueryDenomHashResponse { hash }))
+    }
+
+    async fn escrow_address(
+        &self,
+        request: Request<QueryEscrowAddressRequest>,
+    ) -> Result<Response<QueryEscrowAddressResponse>, Status> {
+        let (port_id, channel_id) =
+            parse_channel(&request.get_ref().port_id, &request.get_ref().channel_id)?;
+
+        let escrow_address = timed_blocking(&self.store, "get_escrow_address", move |store| {
+            store.get_escrow_address(StoreHeight::Latest, &port_id, &channel_id)
+        })
+        .await?;
+        Ok(Response::new(QueryEscrowAddressResponse { escrow_address }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denom_hash() {
+        let denom = PrefixedDenom::from_str("transfer/channel-0/uatom").unwrap();
+        let hash = denom_hash(&denom);
+        assert_eq!(
+            hash,
+            "27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2"
+        );
+
+        assert_eq!(parse_denom_hash(&format!("ibc/{}", hash)).unwrap(), hash);
+        assert_eq!(parse_denom_hash(&hash.to_lowercase()).unwrap(), hash);
+        assert!(parse_denom_hash("ibc/uatom").is_err());
+    }
+}
@@ -1,8 +1,13 @@
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
 use std::str::{from_utf8, FromStr};
+use std::time::Duration;
 
+use ibc::applications::transfer::RawCoin;
+use ibc::core::ics04_channel::packet::Sequence;
+use ibc::core::ics24_host::identifier::{ChannelId, PortId};
 use ibc::core::ics24_host::{path, validate::validate_identifier, Path as IbcPath};
+use ibc_proto::ibc::core::commitment::v1::MerkleProof;
 
 use crate::{Result, ServerError};
 
@@ -81,6 +86,105 @@ pub enum StoreHeight {
     Stable(u64),
 }
 
+/// How list queries treat store data that is inconsistent, such as a key
+/// returned by `get_paths_by_prefix` without a value behind it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConsistencyPolicy {
+    /// Fail the request with a `DATA_LOSS` status.
+    #[default]
+    Strict,
+    /// Log the inconsistent entry and leave it out of the response.
+    Lenient,
+}
+
+/// The ICS-20 transfer module parameters.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferParams {
+    /// Whether tokens may be sent to other chains.
+    pub send_enabled: bool,
+    /// Whether tokens may be received from other chains.
+    pub receive_enabled: bool,
+}
+
+/// The ICS-29 fees a payer escrows for the relaying of a packet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fee {
+    /// Paid to the relayer of the packet.
+    pub recv_fee: Vec<RawCoin>,
+    /// Paid to the relayer of its acknowledgement.
+    pub ack_fee: Vec<RawCoin>,
+    /// Paid to the relayer of its timeout, in place of the other two.
+    pub timeout_fee: Vec<RawCoin>,
+}
+
+/// The fees escrowed for a packet by one payer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PacketFee {
+    pub fee: Fee,
+    /// The address the fees left unpaid are refunded to.
+    pub refund_address: String,
+    /// The relayers allowed to claim the fees, any relayer if empty.
+    pub relayers: Vec<String>,
+}
+
+/// The fees escrowed for a packet not yet relayed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentifiedPacketFees {
+    pub port_id: PortId,
+    pub channel_id: ChannelId,
+    pub sequence: Sequence,
+    pub packet_fees: Vec<PacketFee>,
+}
+
+/// The ICS-27 interchain accounts controller parameters.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IcaControllerParams {
+    /// Whether the chain may open and control interchain accounts.
+    pub controller_enabled: bool,
+}
+
+/// The ICS-27 interchain accounts host parameters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IcaHostParams {
+    /// Whether the chain may host interchain accounts.
+    pub host_enabled: bool,
+    /// The type URLs of the messages interchain accounts may execute.
+    pub allow_messages: Vec<String>,
+}
+
+/// The Cosmos SDK staking parameters relayers read, e.g. to check that the
+/// trusting period of a client is shorter than the unbonding period.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StakingParams {
+    /// How long tokens stay bonded after an unbonding starts.
+    pub unbonding_time: Duration,
+    /// The denom of the staking token.
+    pub bond_denom: String,
+}
+
+/// A Cosmos SDK base account, e.g. the account a relayer signs with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Account {
+    pub address: String,
+    pub account_number: u64,
+    /// The number of transactions the account has sent.
+    pub sequence: u64,
+}
+
+/// The raw bytes committed under a path, as answered to ABCI queries.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawQuery {
+    /// The stored bytes, `None` if nothing is stored under the path.
+    pub value: Option<Vec<u8>>,
+    /// The height the value was read at, i.e. the current height for
+    /// `StoreHeight::Latest`.
+    pub height: u64,
+    /// The ICS-23 proofs of the value, or of its absence, from the IBC store
+    /// up to the app hash. `None` if no proof was asked for or the store
+    /// cannot prove.
+    pub proof: Option<MerkleProof>,
+}
+
 /// A new type representing a valid ICS024 `Path`.
 #[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Path(Vec<Identifier>);
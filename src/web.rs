@@ -0,0 +1,171 @@
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::Either;
+use http::header::{HeaderName, HeaderValue};
+use http::{Method, Request, Response};
+use hyper::Body;
+use tonic::body::BoxBody;
+use tonic_web::{GrpcWebLayer, GrpcWebService};
+use tower::{Layer, Service};
+use tower_http::cors::{AllowOrigin, Cors, CorsLayer};
+
+use crate::ServerError;
+
+/// The CORS policy of gRPC-Web requests, e.g. of a browser dashboard.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CorsPolicy {
+    allowed_origins: Option<Vec<String>>,
+    allow_credentials: bool,
+    max_age: Option<Duration>,
+    exposed_headers: Vec<String>,
+}
+
+impl CorsPolicy {
+    /// Allows requests from any origin, without credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only allows requests from `origin`, e.g. `https://explorer.example`.
+    /// May be called several times.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        self.allowed_origins
+            .get_or_insert_with(Vec::new)
+            .push(origin.into());
+        self
+    }
+
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.allow_credentials = allow;
+        self
+    }
+
+    /// How long browsers may cache the answer to a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Makes the response header `name` readable by the browser app.
+    pub fn expose_header(mut self, name: impl Into<String>) -> Self {
+        self.exposed_headers.push(name.into());
+        self
+    }
+
+    /// Validates the policy into the CORS layer wrapping gRPC-Web services.
+    pub(crate) fn to_cors_layer(&self) -> crate::Result<CorsLayer> {
+        let origin = match &self.allowed_origins {
+            Some(origins) => AllowOrigin::list(
+                origins
+                    .iter()
+                    .map(|origin| header_value("origin", origin))
+                    .collect::<crate::Result<Vec<_>>>()?,
+            ),
+            // Browsers reject a wildcard origin on credentialed requests.
+            None if self.allow_credentials => AllowOrigin::mirror_request(),
+            None => AllowOrigin::any(),
+        };
+        let exposed_headers = EXPOSED_HEADERS
+            .iter()
+            .map(|name| Ok(HeaderName::from_static(name)))
+            .chain(self.exposed_headers.iter().map(|name| {
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                    ServerError::Config(format!("invalid CORS header {:?}: {}", name, e))
+                })
+            }))
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        let layer = CorsLayer::new()
+            .allow_origin(origin)
+            .allow_credentials(self.allow_credentials)
+            .allow_methods([Method::POST])
+            .allow_headers(ALLOWED_HEADERS.map(HeaderName::from_static))
+            .expose_headers(exposed_headers);
+        Ok(match self.max_age {
+            Some(max_age) => layer.max_age(max_age),
+            None => layer,
+        })
+    }
+}
+
+/// The request headers of gRPC-Web clients, and the API key.
+const ALLOWED_HEADERS: [&str; 5] = [
+    "authorization",
+    "content-type",
+    "grpc-timeout",
+    "x-grpc-web",
+    "x-user-agent",
+];
+
+/// The trailers of gRPC-Web responses, which carry the status of the call.
+const EXPOSED_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+fn header_value(kind: &str, value: &str) -> crate::Result<HeaderValue> {
+    HeaderValue::from_str(value)
+        .map_err(|e| ServerError::Config(format!("invalid CORS {} {:?}: {}", kind, value, e)))
+}
+
+/// A layer answering gRPC-Web requests as well when `cors` is set. It goes
+/// outside the authentication and rate limit layers, so that their
+/// rejections carry CORS headers and preflight requests, which browsers send
+/// without credentials, are answered before them.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MaybeGrpcWebLayer {
+    cors: Option<CorsLayer>,
+}
+
+impl MaybeGrpcWebLayer {
+    pub(crate) fn new(cors: Option<CorsLayer>) -> Self {
+        Self { cors }
+    }
+}
+
+impl<S> Layer<S> for MaybeGrpcWebLayer
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+{
+    type Service = MaybeGrpcWeb<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        match &self.cors {
+            Some(cors) => {
+                MaybeGrpcWeb::Web(Box::new(cors.layer(GrpcWebLayer::new().layer(service))))
+            }
+            None => MaybeGrpcWeb::Plain(service),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MaybeGrpcWeb<S> {
+    Plain(S),
+    Web(Box<Cors<GrpcWebService<S>>>),
+}
+
+impl<S> Service<Request<Body>> for MaybeGrpcWeb<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Either<S::Future, <Cors<GrpcWebService<S>> as Service<Request<Body>>>::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self {
+            MaybeGrpcWeb::Plain(service) => service.poll_ready(cx),
+            MaybeGrpcWeb::Web(service) => service.poll_ready(cx),
+        }
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        match self {
+            MaybeGrpcWeb::Plain(service) => Either::Left(service.call(req)),
+            MaybeGrpcWeb::Web(service) => Either::Right(service.call(req)),
+        }
+    }
+}